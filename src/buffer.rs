@@ -0,0 +1,525 @@
+use std::cmp::min;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter};
+use std::ops::Range;
+use std::os::unix::fs::{fchown, DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use ropey::{Rope, RopeSlice};
+
+use crate::undo::{Edit, UndoTree};
+
+/// a file's text and metadata, shared by every window showing it
+///
+/// the text is backed by a rope, so inserts, removals and line
+/// lookups are O(log n) no matter how large the file is
+///
+/// lines are split on '\n' only, a trailing newline yields an empty
+/// last line. positions are (line, col) pairs in chars, the newline
+/// itself is never part of a line
+#[derive(Debug)]
+pub struct Buffer {
+    text: Rope,
+    pub path: PathBuf,
+    /// modification time of the file when it was last read or written
+    mtime: Option<SystemTime>,
+    /// number of the change that produced the current text, every edit
+    /// gets a new one and undo goes back to older ones
+    changenr: usize,
+    /// highest change number handed out so far
+    last_changenr: usize,
+    /// change number of the text that was last read or written
+    saved_changenr: usize,
+    undo: UndoTree,
+}
+
+impl Buffer {
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn new(contents: &str, path: PathBuf) -> Self {
+        Self {
+            text: Rope::from_str(contents),
+            path,
+            mtime: None,
+            changenr: 0,
+            last_changenr: 0,
+            saved_changenr: 0,
+            undo: UndoTree::default(),
+        }
+    }
+
+    pub fn from_reader<R: io::Read>(reader: R, path: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            text: Rope::from_reader(reader)?,
+            path,
+            mtime: None,
+            changenr: 0,
+            last_changenr: 0,
+            saved_changenr: 0,
+            undo: UndoTree::default(),
+        })
+    }
+
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mtime = file.metadata()?.modified().ok();
+        let mut buffer = Self::from_reader(BufReader::new(file), path.to_path_buf())?;
+        buffer.mtime = mtime;
+        Ok(buffer)
+    }
+
+    /// true when the file was written by someone else since we read it
+    pub fn changed_on_disk(&self) -> bool {
+        let current = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        self.mtime.is_some() && current.is_some() && current != self.mtime
+    }
+
+    /// writes the buffer back to its own file
+    pub fn save(&mut self) -> io::Result<usize> {
+        let path = self.path.clone();
+        let written = self.write_file(&path)?;
+        self.mtime = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        self.saved_changenr = self.changenr;
+        Ok(written)
+    }
+
+    /// writes the text to `path` through a temporary file in the same
+    /// directory that is then renamed over it, so that a failed write
+    /// never leaves a truncated file behind. an existing file keeps its
+    /// mode and owner. returns the number of bytes written
+    pub fn write_file(&self, path: &Path) -> io::Result<usize> {
+        // write through symlinks instead of replacing them
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let (dir, tmp) = temp_file(&path)?;
+        let res = self
+            .write_tmp(&tmp, fs::metadata(&path).ok())
+            .and_then(|_| fs::rename(&tmp, &path));
+        if let Err(e) = res {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        // the rename only survives a crash once the directory is synced
+        File::open(&dir)?.sync_all()?;
+        Ok(self.len_bytes())
+    }
+
+    fn write_tmp(&self, tmp: &Path, meta: Option<fs::Metadata>) -> io::Result<()> {
+        let file = OpenOptions::new().write(true).create_new(true).open(tmp)?;
+        if let Some(meta) = meta {
+            // only root may give a file away, fall back to keeping the group
+            if fchown(&file, Some(meta.uid()), Some(meta.gid())).is_err() {
+                let _ = fchown(&file, None, Some(meta.gid()));
+            }
+            file.set_permissions(meta.permissions())?;
+        }
+        let mut writer = BufWriter::new(file);
+        self.write_to(&mut writer)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()
+    }
+
+    /// the path as given on the command line, for the status line
+    pub fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    pub fn write_to<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        self.text.write_to(writer)
+    }
+
+    /// true when the text differs from what was last read or written
+    pub fn modified(&self) -> bool {
+        self.changenr != self.saved_changenr
+    }
+
+    pub fn changenr(&self) -> usize {
+        self.changenr
+    }
+
+    fn touch(&mut self) {
+        self.last_changenr += 1;
+        self.changenr = self.last_changenr;
+    }
+
+    /// keeps the edit about to replace `removed` chars at `at` with
+    /// `inserted` for undo
+    fn record(&mut self, at: usize, removed: usize, inserted: &str) {
+        let line = self.text.char_to_line(at);
+        let edit = Edit {
+            at,
+            removed: self.text.slice(at..at + removed).to_string(),
+            inserted: inserted.to_string(),
+        };
+        let text = &self.text;
+        self.undo.record(edit, line, || {
+            text.line(line).chars().take_while(|&c| c != '\n').collect()
+        });
+    }
+
+    pub fn undo_tree(&self) -> &UndoTree {
+        &self.undo
+    }
+
+    /// where the cursor is before the next change, for undo to put it back
+    pub fn set_undo_cursor(&mut self, line: usize, col: usize) {
+        self.undo.set_cursor((line, col));
+    }
+
+    /// ends a change, the edits since the last one are undone together
+    pub fn commit_undo(&mut self) -> bool {
+        self.undo.commit(self.changenr)
+    }
+
+    /// undoes and redoes changes until the text is in state `seq`,
+    /// returns the changes undone or redone as (state, redo)
+    pub fn undo_to(&mut self, seq: usize) -> Vec<(usize, bool)> {
+        let steps = self.undo.goto(seq);
+        for &(seq, redo) in &steps {
+            let edits = &self.undo.change(seq).edits;
+            if redo {
+                for edit in edits {
+                    let end = edit.at + edit.removed.chars().count();
+                    self.text.remove(edit.at..end);
+                    self.text.insert(edit.at, &edit.inserted);
+                }
+            } else {
+                for edit in edits.iter().rev() {
+                    let end = edit.at + edit.inserted.chars().count();
+                    self.text.remove(edit.at..end);
+                    self.text.insert(edit.at, &edit.removed);
+                }
+            }
+        }
+        self.changenr = self.undo.change(seq).changenr;
+        steps
+    }
+
+    /// FNV-1a hash of the text, undo files are only used for the text
+    /// they were written for
+    pub fn hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for chunk in self.text.chunks() {
+            for &b in chunk.as_bytes() {
+                hash = (hash ^ b as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+
+    /// writes the undo history to its file in `dir`, through a temporary
+    /// file like `write_file`. the history has all text ever deleted, so
+    /// it may only be read by those who may read the file itself
+    pub fn write_undo(&self, dir: &Path) -> io::Result<()> {
+        let path = undo_file(dir, &self.path);
+        if let Some(parent) = path.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(parent)?;
+        }
+        let (_, tmp) = temp_file(&path)?;
+        let res = self
+            .write_undo_tmp(&tmp)
+            .and_then(|_| fs::rename(&tmp, &path));
+        if res.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        res
+    }
+
+    fn write_undo_tmp(&self, tmp: &Path) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(tmp)?;
+        if let Ok(meta) = fs::metadata(&self.path) {
+            let mode = meta.permissions().mode() & 0o666;
+            file.set_permissions(fs::Permissions::from_mode(mode))?;
+        }
+        let mut writer = BufWriter::new(file);
+        self.undo.write_to(&mut writer, self.hash())?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()
+    }
+
+    /// reads the undo history from its file in `dir`, false when there
+    /// is none or it was written for other text
+    pub fn read_undo(&mut self, dir: &Path) -> io::Result<bool> {
+        let file = match File::open(undo_file(dir, &self.path)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let Some(undo) = UndoTree::read_from(BufReader::new(file), self.hash(), self.len_chars())?
+        else {
+            return Ok(false);
+        };
+        // the loaded states are numbered as changes, the text is the
+        // current one and as it is on disk
+        self.changenr = undo.seq();
+        self.saved_changenr = undo.seq();
+        self.last_changenr = undo.last();
+        self.undo = undo;
+        Ok(true)
+    }
+
+    /// `U`, puts back the line the last changes were in as it was before
+    /// them, returns the line
+    pub fn undo_line(&mut self) -> Option<usize> {
+        let (line, text) = self.undo.take_line()?;
+        if line >= self.line_count() {
+            return None;
+        }
+        let start = self.line_to_char(line);
+        self.remove(start..start + self.line_len(line));
+        self.insert(start, &text);
+        Some(line)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.text.len_lines()
+    }
+
+    /// the last line of the text, the empty one after a final newline
+    /// doesn't count
+    pub fn last_line(&self) -> usize {
+        let count = self.line_count();
+        let len = self.text.len_chars();
+        if count > 1 && self.text.char(len - 1) == '\n' {
+            count - 2
+        } else {
+            count - 1
+        }
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.text.len_chars()
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.text.len_bytes()
+    }
+
+    /// the contents of line `ix` without its newline
+    pub fn line(&self, ix: usize) -> RopeSlice<'_> {
+        let line = self.text.line(ix);
+        let len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            line.slice(..len - 1)
+        } else {
+            line
+        }
+    }
+
+    pub fn line_len(&self, ix: usize) -> usize {
+        self.line(ix).len_chars()
+    }
+
+    pub fn char_at(&self, line: usize, col: usize) -> Option<char> {
+        self.line(line).get_char(col)
+    }
+
+    /// the char at offset `char_ix`, None at the end of the text
+    pub fn get_char(&self, char_ix: usize) -> Option<char> {
+        self.text.get_char(char_ix)
+    }
+
+    pub fn line_to_char(&self, line: usize) -> usize {
+        self.text.line_to_char(line)
+    }
+
+    pub fn char_to_line(&self, char_ix: usize) -> usize {
+        self.text.char_to_line(char_ix)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn line_to_byte(&self, line: usize) -> usize {
+        self.text.line_to_byte(line)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn byte_to_line(&self, byte_ix: usize) -> usize {
+        self.text.byte_to_line(byte_ix)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn char_to_byte(&self, char_ix: usize) -> usize {
+        self.text.char_to_byte(char_ix)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn byte_to_char(&self, byte_ix: usize) -> usize {
+        self.text.byte_to_char(byte_ix)
+    }
+
+    /// char offset of (line, col), col is clamped to the line length
+    pub fn pos_to_char(&self, line: usize, col: usize) -> usize {
+        self.line_to_char(line) + min(col, self.line_len(line))
+    }
+
+    pub fn char_to_pos(&self, char_ix: usize) -> (usize, usize) {
+        let line = self.char_to_line(char_ix);
+        (line, char_ix - self.line_to_char(line))
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> RopeSlice<'_> {
+        self.text.slice(range)
+    }
+
+    pub fn insert(&mut self, char_ix: usize, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        self.record(char_ix, 0, s);
+        self.text.insert(char_ix, s);
+        self.touch();
+    }
+
+    pub fn remove(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        self.record(range.start, range.len(), "");
+        self.text.remove(range);
+        self.touch();
+    }
+
+    pub fn insert_char(&mut self, line: usize, col: usize, c: char) {
+        let ix = self.pos_to_char(line, col);
+        self.record(ix, 0, c.encode_utf8(&mut [0; 4]));
+        self.text.insert_char(ix, c);
+        self.touch();
+    }
+
+    /// removes the char at (line, col), does nothing past the end of the line
+    pub fn remove_char(&mut self, line: usize, col: usize) {
+        if col < self.line_len(line) {
+            let ix = self.line_to_char(line) + col;
+            self.record(ix, 1, "");
+            self.text.remove(ix..ix + 1);
+            self.touch();
+        }
+    }
+
+    /// breaks line `line` in two at `col`
+    pub fn split_line(&mut self, line: usize, col: usize) {
+        self.insert_char(line, col, '\n');
+    }
+
+    /// joins line `line` with the one after it
+    pub fn join_lines(&mut self, line: usize) {
+        if line + 1 < self.line_count() {
+            let ix = self.line_to_char(line) + self.line_len(line);
+            self.record(ix, 1, "");
+            self.text.remove(ix..ix + 1);
+            self.touch();
+        }
+    }
+}
+
+/// the directory of `path` and a temporary file in it to write to
+/// before renaming it over `path`
+fn temp_file(path: &Path) -> io::Result<(PathBuf, PathBuf)> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "is a directory"))?;
+    let mut tmpname = OsString::from(".");
+    tmpname.push(name);
+    tmpname.push(format!(".{}~", std::process::id()));
+    let tmp = dir.join(tmpname);
+    Ok((dir, tmp))
+}
+
+/// the undo file for `path` in `dir`. "." keeps it next to the file,
+/// elsewhere it is named after the whole path with its slashes as '%'
+fn undo_file(dir: &Path, path: &Path) -> PathBuf {
+    let path = fs::canonicalize(path)
+        .or_else(|_| std::path::absolute(path))
+        .unwrap_or_else(|_| path.to_path_buf());
+    if dir == Path::new(".") {
+        let mut name = OsString::from(".");
+        name.push(path.file_name().unwrap_or_default());
+        name.push(".un~");
+        return path.with_file_name(name);
+    }
+    dir.join(path.to_string_lossy().replace('/', "%"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> Buffer {
+        Buffer::new(text, PathBuf::from("test.txt"))
+    }
+
+    #[test]
+    fn lines_split_on_newline_only() {
+        let buf = buffer("one\ntwo\r\n\nlast\n");
+        assert_eq!(buf.line_count(), 5);
+        assert_eq!(buf.line(1).to_string(), "two\r");
+        assert_eq!(buf.line_len(2), 0);
+        assert_eq!(buf.line(4).to_string(), "");
+    }
+
+    #[test]
+    fn conversions_count_chars_and_bytes_apart() {
+        let buf = buffer("aé\n日本\nz");
+        assert_eq!(buf.len_chars(), 7);
+        assert_eq!(buf.len_bytes(), 12);
+        assert_eq!(buf.line_to_char(2), 6);
+        assert_eq!(buf.line_to_byte(2), 11);
+        assert_eq!(buf.char_to_byte(4), 7);
+        assert_eq!(buf.byte_to_char(7), 4);
+        assert_eq!(buf.char_to_line(5), 1);
+        assert_eq!(buf.byte_to_line(9), 1);
+        assert_eq!(buf.pos_to_char(1, 1), 4);
+        assert_eq!(buf.pos_to_char(0, 9), 2);
+        assert_eq!(buf.char_to_pos(4), (1, 1));
+    }
+
+    #[test]
+    fn edits_change_the_text_and_changenr() {
+        let mut buf = buffer("ab\ncd");
+        assert!(!buf.modified());
+        buf.insert_char(0, 1, 'x');
+        buf.split_line(1, 1);
+        assert_eq!(buf.slice(0..buf.len_chars()).to_string(), "axb\nc\nd");
+        buf.join_lines(0);
+        buf.remove_char(0, 0);
+        assert_eq!(buf.slice(0..buf.len_chars()).to_string(), "xbc\nd");
+        assert!(buf.modified());
+        assert_eq!(buf.changenr(), 4);
+    }
+
+    #[test]
+    fn undo_files_are_as_private_as_the_file() {
+        let dir = std::env::temp_dir().join(format!("ri-undo-{}", std::process::id()));
+        let undodir = dir.join("undo");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("secret.txt");
+        fs::write(&path, "one\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        let mut buf = Buffer::open(&path).unwrap();
+        buf.insert(0, "zero\n");
+        buf.commit_undo();
+        buf.save().unwrap();
+        buf.write_undo(&undodir).unwrap();
+        let undo = undo_file(&undodir, &path);
+        assert_eq!(fs::metadata(&undo).unwrap().mode() & 0o777, 0o600);
+        assert_eq!(fs::metadata(&undodir).unwrap().mode() & 0o777, 0o700);
+        // nothing is left of the temporary file
+        assert_eq!(fs::read_dir(&undodir).unwrap().count(), 1);
+        let mut again = Buffer::open(&path).unwrap();
+        assert!(again.read_undo(&undodir).unwrap());
+        again.undo_to(0);
+        assert_eq!(again.slice(0..again.len_chars()).to_string(), "one\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
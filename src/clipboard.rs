@@ -0,0 +1,83 @@
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// the programs that copy to the system clipboard, or to the primary
+/// selection when `primary`, in the order they are tried
+fn copy_commands(primary: bool) -> [Vec<&'static str>; 3] {
+    let (xclip, xsel) = selections(primary);
+    [
+        if primary {
+            vec!["wl-copy", "--primary"]
+        } else {
+            vec!["wl-copy"]
+        },
+        vec!["xclip", "-selection", xclip],
+        vec!["xsel", xsel, "--input"],
+    ]
+}
+
+/// the programs that paste, like `copy_commands`
+fn paste_commands(primary: bool) -> [Vec<&'static str>; 3] {
+    let (xclip, xsel) = selections(primary);
+    [
+        if primary {
+            vec!["wl-paste", "--no-newline", "--primary"]
+        } else {
+            vec!["wl-paste", "--no-newline"]
+        },
+        vec!["xclip", "-selection", xclip, "-o"],
+        vec!["xsel", xsel, "--output"],
+    ]
+}
+
+/// how xclip and xsel name the selection
+fn selections(primary: bool) -> (&'static str, &'static str) {
+    if primary {
+        ("primary", "--primary")
+    } else {
+        ("clipboard", "--clipboard")
+    }
+}
+
+/// true in a session over ssh, where the programs above would reach the
+/// clipboard of the remote machine instead of the one in front of the user
+pub fn remote() -> bool {
+    env::var_os("SSH_TTY").is_some() || env::var_os("SSH_CONNECTION").is_some()
+}
+
+/// puts `text` on the system clipboard with the first program that is
+/// there and works, false when none did
+pub fn copy(text: &str, primary: bool) -> bool {
+    copy_commands(primary).iter().any(|cmd| {
+        let child = Command::new(cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let Ok(mut child) = child else {
+            return false;
+        };
+        let written = child
+            .stdin
+            .take()
+            .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        child.wait().is_ok_and(|status| status.success()) && written
+    })
+}
+
+/// what is on the system clipboard, None when no program could get it
+pub fn paste(primary: bool) -> Option<String> {
+    paste_commands(primary).iter().find_map(|cmd| {
+        let out = Command::new(cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        out.status
+            .success()
+            .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+    })
+}
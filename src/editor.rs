@@ -0,0 +1,1024 @@
+use std::{collections::VecDeque, path::Path};
+
+use rustix::io::Errno;
+
+mod macros;
+mod normal;
+mod operator;
+mod repeat;
+mod replace;
+#[cfg(test)]
+mod tests;
+mod undo;
+mod visual;
+
+use crate::buffer::Buffer;
+use crate::input::{EditorKey, InputDecoder, Modifiers, MouseButton, MouseEvent, MouseKind};
+use crate::motion::Find;
+use crate::normal::Parser;
+use crate::options::Options;
+use crate::register::{Kind, Registers};
+use crate::screen::{Frame, ERROR, GUTTER, STATUS};
+use crate::terminal::{Event, Terminal};
+use crate::window::{Selection, Window};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EditorMode {
+    Normal,
+    Insert,
+    Command,
+    Visual,
+    VisualLine,
+    VisualBlock,
+    /// typed chars overwrite the text, entered with `R`
+    Replace,
+    /// like replace, but overwriting screen columns, entered with `gR`
+    VirtualReplace,
+}
+
+impl EditorMode {
+    /// true in the modes where typing puts text into the buffer
+    pub fn is_insert(&self) -> bool {
+        matches!(
+            self,
+            EditorMode::Insert | EditorMode::Replace | EditorMode::VirtualReplace
+        )
+    }
+
+    pub fn is_visual(&self) -> bool {
+        matches!(
+            self,
+            EditorMode::Visual | EditorMode::VisualLine | EditorMode::VisualBlock
+        )
+    }
+}
+
+/// lines scrolled by one notch of the mouse wheel
+const MOUSE_SCROLL_LINES: isize = 3;
+
+impl std::fmt::Display for EditorMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = match self {
+            EditorMode::Normal => "normal",
+            EditorMode::Insert => "insert",
+            EditorMode::Command => "",
+            EditorMode::Visual => "visual",
+            EditorMode::VisualLine => "visual line",
+            EditorMode::VisualBlock => "visual block",
+            EditorMode::Replace => "replace",
+            EditorMode::VirtualReplace => "virtual replace",
+        };
+        // with background color pink and foreground color white
+        write!(f, "{}", mode)
+    }
+}
+
+/// owns every open buffer and the windows showing them, the mode and
+/// the command line
+#[derive(Debug)]
+pub struct Editor {
+    buffers: Vec<Buffer>,
+    windows: Vec<Window>,
+    /// index of the window with the cursor
+    current: usize,
+    screenrows: u16,
+    screencols: u16,
+    term: Box<dyn Terminal>,
+    input: InputDecoder,
+    options: Options,
+    cmd: String,
+    cmdix: usize,
+    /// shown on the bottom line until the next key press
+    message: String,
+    message_is_error: bool,
+    mode: EditorMode,
+    /// normal mode keys typed so far
+    pending: Parser,
+    insertion: normal::Insertion,
+    /// the last `f`, `F`, `t` or `T`, for `;` and `,`
+    last_find: Option<Find>,
+    /// yanked and deleted text, and the other registers
+    registers: Registers,
+    /// text for the terminal to put on the clipboard with the next frame,
+    /// and whether it goes on the primary selection
+    copied: Option<(String, bool)>,
+    /// the last visual selection, for `gv`
+    last_visual: Option<visual::Visual>,
+    /// the last change made in normal mode, for `.`
+    last_change: Option<repeat::Change>,
+    /// the register `q` is recording into and the keys typed so far
+    recording: Option<(char, Vec<EditorKey>)>,
+    /// the register `@` ran last, for `@@`
+    last_macro: Option<char>,
+    /// keys of the macros being run, handled before anything typed
+    replay: VecDeque<EditorKey>,
+    /// the key of an alt+key, read after the ESC it was split into
+    after_alt: Option<EditorKey>,
+}
+
+impl Editor {
+    pub fn new(buffer: Buffer, term: Box<dyn Terminal>) -> Self {
+        let mut editor = Self {
+            buffers: vec![buffer],
+            windows: vec![Window::new(0)],
+            current: 0,
+            screenrows: 0,
+            screencols: 0,
+            term,
+            input: InputDecoder::default(),
+            options: Options::default(),
+            mode: EditorMode::Normal,
+            pending: Parser::default(),
+            insertion: normal::Insertion::default(),
+            last_find: None,
+            registers: Registers::default(),
+            copied: None,
+            last_visual: None,
+            last_change: None,
+            recording: None,
+            last_macro: None,
+            replay: VecDeque::new(),
+            after_alt: None,
+            cmd: String::new(),
+            cmdix: 0,
+            message: String::new(),
+            message_is_error: false,
+        };
+        editor.read_undo(0);
+        editor
+    }
+
+    /// asks the terminal for its size, only done at startup and after it
+    /// was resized
+    fn set_size(&mut self) {
+        let prev = (self.screenrows, self.screencols);
+        (self.screenrows, self.screencols) = self.term.size().unwrap_or((24, 80));
+        if prev != (self.screenrows, self.screencols) {
+            self.layout();
+        }
+    }
+
+    /// stacks the windows on top of each other above the bottom line,
+    /// once there is more than one each gets a status line under it
+    fn layout(&mut self) {
+        let n = self.windows.len();
+        let status = self.status_rows();
+        let avail = (self.screenrows as usize).saturating_sub(1 + n * status);
+        let each = avail / n;
+        let mut top = 0;
+        for (i, win) in self.windows.iter_mut().enumerate() {
+            win.top = top;
+            win.height = if i == n - 1 {
+                avail - each * (n - 1)
+            } else {
+                each
+            }
+            .max(1);
+            win.width = self.screencols as usize;
+            top += win.height + status;
+        }
+    }
+
+    /// rows taken by the status line of each window
+    fn status_rows(&self) -> usize {
+        if self.windows.len() > 1 {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn refresh_screen(&mut self) -> Result<(), Errno> {
+        self.scroll_windows();
+        let width = self.screencols as usize;
+        let bottom = (self.screenrows as usize).saturating_sub(1);
+        let mut frame = Frame::new(self.screenrows as usize, width);
+        frame.clipboard = self.copied.take();
+        for (i, win) in self.windows.iter().enumerate() {
+            let selection = if i == self.current {
+                self.selection()
+            } else {
+                None
+            };
+            win.draw(&self.buffers[win.buf], selection, &mut frame);
+            if self.status_rows() > 0 {
+                let style = if i == self.current { STATUS } else { GUTTER };
+                let row = win.top + win.height;
+                let name = status_name(&self.buffers[win.buf]);
+                let end = frame.put(row, 0, name.chars(), style);
+                frame.fill(row, end, width, style);
+            }
+        }
+        let win = &self.windows[self.current];
+        if self.mode != EditorMode::Command {
+            let dashes = "-".repeat(win.cx_base - 2);
+            let mut col = frame.put(bottom, 0, dashes.chars(), GUTTER);
+            col = frame.put(bottom, col, self.mode.to_string().chars(), STATUS);
+            if let Some((name, _)) = self.recording {
+                let recording = format!("  recording @{}", name);
+                col = frame.put(bottom, col, recording.chars(), STATUS);
+            }
+            if self.status_rows() == 0 {
+                let name = status_name(&self.buffers[win.buf]);
+                col = frame.put(bottom, col, "  ".chars().chain(name.chars()), STATUS);
+            }
+            let style = if self.message_is_error { ERROR } else { STATUS };
+            if self.message.contains('\n') {
+                // a message of several lines goes over the windows above
+                // the bottom line
+                let lines: Vec<&str> = self.message.lines().collect();
+                let first = bottom.saturating_sub(lines.len());
+                for (row, line) in (first..bottom).zip(&lines[lines.len() - (bottom - first)..]) {
+                    let end = frame.put(row, 0, line.chars(), style);
+                    frame.fill(row, end, width, style);
+                }
+            } else if !self.message.is_empty() {
+                col = frame.put(bottom, col, "  ".chars().chain(self.message.chars()), style);
+            }
+            frame.fill(bottom, col, width, STATUS);
+            let (y, x) = win.screen_cursor(&self.buffers[win.buf]);
+            frame.cursor = Some((y - 1, x - 1));
+        } else {
+            let col = frame.put(bottom, 0, ": ".chars().chain(self.cmd.chars()), STATUS);
+            frame.fill(bottom, col, width, STATUS);
+            frame.cursor = Some((bottom, self.cmdix + 2));
+        }
+        self.term.draw(frame)
+    }
+
+    /// keeps the cursor of every window in its buffer and on the screen,
+    /// a change in one window can leave another one past the end
+    fn scroll_windows(&mut self) {
+        for win in self.windows.iter_mut() {
+            win.tabstop = self.options.tabstop as usize;
+            win.scroll(&self.buffers[win.buf]);
+        }
+    }
+
+    /// true when Ctrl-C was typed while a macro runs, what else was typed
+    /// is handled once the macro is done
+    fn interrupted(&mut self) -> Result<bool, Errno> {
+        match self.term.read(0)? {
+            Event::Input(bytes) if bytes.contains(&b'\x03') => return Ok(true),
+            Event::Input(bytes) => self.input.push(&bytes),
+            Event::Resize => self.set_size(),
+            Event::Stop => self.suspend(),
+            Event::Continue => self.resume(),
+            Event::Timeout | Event::Eof => {}
+        }
+        Ok(false)
+    }
+
+    /// the next key, or None when the terminal had something else to say
+    /// first so that the caller gets a chance to redraw. alt+key comes as
+    /// ESC and then the key, which is what it means in vim too, and how
+    /// a macro records it
+    fn read_editor_key(&mut self) -> Result<Option<EditorKey>, Errno> {
+        if let Some(key) = self.after_alt.take() {
+            return Ok(Some(key));
+        }
+        let key = self.read_key()?;
+        if let Some(rest) = key.as_ref().and_then(EditorKey::without_alt) {
+            self.after_alt = Some(rest);
+            return Ok(Some(EditorKey::K(b'\x1b')));
+        }
+        Ok(key)
+    }
+
+    fn read_key(&mut self) -> Result<Option<EditorKey>, Errno> {
+        if let Some(key) = self.input.next_key(false) {
+            return Ok(Some(key));
+        }
+        let timeout = if self.input.is_pending() {
+            self.options.ttimeoutlen.min(i32::MAX as u64) as i32
+        } else {
+            -1
+        };
+        // a half read escape sequence is kept over anything but a timeout
+        Ok(match self.term.read(timeout)? {
+            Event::Input(bytes) => {
+                self.input.push(&bytes);
+                self.input.next_key(false)
+            }
+            Event::Timeout => self.input.next_key(true),
+            Event::Resize => {
+                self.set_size();
+                None
+            }
+            Event::Stop => {
+                self.suspend();
+                None
+            }
+            Event::Continue => {
+                self.resume();
+                None
+            }
+            Event::Eof => match self.input.next_key(true) {
+                Some(key) => Some(key),
+                None => return Err(Errno::IO),
+            },
+        })
+    }
+
+    /// the focused window and the buffer it shows
+    fn current(&mut self) -> (&mut Window, &mut Buffer) {
+        let win = &mut self.windows[self.current];
+        let buf = &mut self.buffers[win.buf];
+        (win, buf)
+    }
+
+    /// index of the buffer for `path`, opening it if it isn't open yet
+    fn find_or_open(&mut self, path: &str) -> Result<usize, String> {
+        if let Some(ix) = self.buffers.iter().position(|b| b.path == Path::new(path)) {
+            return Ok(ix);
+        }
+        let buffer = Buffer::open(Path::new(path))
+            .map_err(|e| format!("E484: Can't open file {}: {}", path, e))?;
+        self.buffers.push(buffer);
+        self.read_undo(self.buffers.len() - 1);
+        Ok(self.buffers.len() - 1)
+    }
+
+    /// picks up the undo history of buffer `ix` left by an earlier session
+    fn read_undo(&mut self, ix: usize) {
+        if !self.options.undofile {
+            return;
+        }
+        if let Err(e) = self.buffers[ix].read_undo(&self.options.undo_dir()) {
+            self.message = format!("E822: Cannot read undo file: {}", e);
+            self.message_is_error = true;
+        }
+    }
+
+    /// closes the focused window, returns true when it was the last one
+    fn close_window(&mut self) -> bool {
+        if self.windows.len() == 1 {
+            return true;
+        }
+        self.windows.remove(self.current);
+        if self.current == self.windows.len() {
+            self.current -= 1;
+        }
+        self.layout();
+        false
+    }
+
+    /// fails when a buffer has unsaved changes, the current one first
+    fn check_modified(&self) -> Result<(), String> {
+        let cur = self.windows[self.current].buf;
+        if self.buffers[cur].modified() {
+            return Err("E37: No write since last change (add ! to override)".to_string());
+        }
+        if let Some(buffer) = self.buffers.iter().find(|b| b.modified()) {
+            return Err(format!(
+                "E162: No write since last change for buffer \"{}\"",
+                buffer.name()
+            ));
+        }
+        Ok(())
+    }
+
+    /// `:q`, closing the last window exits the editor, which is refused
+    /// while any buffer has unsaved changes unless `bang` is set
+    fn quit(&mut self, bang: bool) -> Result<bool, String> {
+        if self.windows.len() == 1 && !bang {
+            self.check_modified()?;
+        }
+        Ok(self.close_window())
+    }
+
+    /// `:qa`, exits the editor no matter how many windows are open
+    fn quit_all(&mut self, bang: bool) -> Result<bool, String> {
+        if !bang {
+            self.check_modified()?;
+        }
+        Ok(true)
+    }
+
+    /// writes buffer `ix` back to its own file
+    fn save_buffer(&mut self, ix: usize, bang: bool) -> Result<(), String> {
+        let buffer = &mut self.buffers[ix];
+        if buffer.changed_on_disk() && !bang {
+            return Err(
+                "WARNING: The file has been changed since reading it (add ! to override)"
+                    .to_string(),
+            );
+        }
+        let name = buffer.name();
+        let bytes = buffer
+            .save()
+            .map_err(|e| format!("E212: Can't write \"{}\": {}", name, e))?;
+        self.message = written_message(&name, buffer, bytes);
+        if self.options.undofile {
+            buffer
+                .write_undo(&self.options.undo_dir())
+                .map_err(|e| format!("E828: Cannot open undo file for writing: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// `:w [path]`, writes the current buffer to its own file or to `path`
+    fn write(&mut self, arg: &str, bang: bool) -> Result<(), String> {
+        let cur = self.windows[self.current].buf;
+        let path = Path::new(arg);
+        if arg.is_empty() || path == self.buffers[cur].path {
+            return self.save_buffer(cur, bang);
+        }
+        if path.exists() && !bang {
+            return Err("E13: File exists (add ! to override)".to_string());
+        }
+        let buffer = &self.buffers[cur];
+        let bytes = buffer
+            .write_file(path)
+            .map_err(|e| format!("E212: Can't write \"{}\": {}", arg, e))?;
+        self.message = written_message(arg, buffer, bytes);
+        Ok(())
+    }
+
+    /// `:wa`, writes every buffer that has unsaved changes
+    fn write_all(&mut self, bang: bool) -> Result<(), String> {
+        for ix in 0..self.buffers.len() {
+            if self.buffers[ix].modified() {
+                self.save_buffer(ix, bang)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `:e [path]`, shows `path` in the current window, without a path
+    /// the current buffer is read again from its file
+    fn edit(&mut self, arg: &str, bang: bool) -> Result<(), String> {
+        let cur = self.windows[self.current].buf;
+        if arg.is_empty() {
+            if self.buffers[cur].modified() && !bang {
+                return Err("E37: No write since last change (add ! to override)".to_string());
+            }
+            let path = self.buffers[cur].path.clone();
+            self.buffers[cur] = Buffer::open(&path)
+                .map_err(|e| format!("E484: Can't open file {}: {}", path.display(), e))?;
+            self.read_undo(cur);
+            return Ok(());
+        }
+        let ix = self.find_or_open(arg)?;
+        self.windows[self.current].show(ix);
+        Ok(())
+    }
+
+    /// `:sp [path]`, opens a new window above the current one
+    fn split(&mut self, arg: &str) -> Result<(), String> {
+        let ix = if arg.is_empty() {
+            self.windows[self.current].buf
+        } else {
+            self.find_or_open(arg)?
+        };
+        self.windows.insert(self.current, Window::new(ix));
+        self.layout();
+        Ok(())
+    }
+
+    fn wincmd(&mut self, c: &str) -> Result<bool, String> {
+        let n = self.windows.len();
+        match c {
+            "w" | "j" => self.current = (self.current + 1) % n,
+            "W" | "k" => self.current = (self.current + n - 1) % n,
+            "q" | "c" => return self.quit(false),
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    /// `:set`, shows the values asked for
+    fn set(&mut self, arg: &str) -> Result<(), String> {
+        let undofile = self.options.undofile;
+        let mut shown = vec![];
+        for arg in arg.split_whitespace() {
+            if let Some(value) = self.options.set(arg)? {
+                shown.push(value);
+            }
+        }
+        self.term.set_mouse(self.options.mouse);
+        if self.options.undofile && !undofile {
+            // the history of files opened before is there too, as long as
+            // they weren't changed since
+            for ix in 0..self.buffers.len() {
+                if self.buffers[ix].undo_tree().last() == 0 {
+                    self.read_undo(ix);
+                }
+            }
+        }
+        self.message = shown.join("  ");
+        Ok(())
+    }
+
+    /// Ctrl-Z and `:suspend`, gives the terminal back to the shell until
+    /// the process is continued
+    fn suspend(&mut self) {
+        if let Err(e) = self.term.suspend() {
+            self.message = e.to_string();
+            self.message_is_error = true;
+        }
+        self.resume();
+    }
+
+    /// takes the terminal over again after the process was stopped, the
+    /// size may have changed in the meantime
+    fn resume(&mut self) {
+        if let Err(e) = self.term.enter_raw() {
+            self.message = e.to_string();
+            self.message_is_error = true;
+        }
+        self.term.set_mouse(self.options.mouse);
+        self.set_size();
+    }
+
+    /// `:registers`, lists what the registers hold, only those named in
+    /// `arg` when given
+    fn list_registers(&mut self, arg: &str) {
+        let width = (self.screencols as usize).saturating_sub(10);
+        let name = self.current().1.name();
+        let mut lines = vec!["Type Name Content".to_string()];
+        for (name, register) in self.registers.list(&name) {
+            if !arg.is_empty() && !arg.contains(name) {
+                continue;
+            }
+            let kind = match register.kind {
+                Kind::Charwise => 'c',
+                Kind::Linewise => 'l',
+                Kind::Blockwise => 'b',
+            };
+            let content: String = register
+                .text
+                .chars()
+                .flat_map(|c| match c {
+                    c if c.is_ascii_control() => vec!['^', ((c as u8) ^ 0x40) as char],
+                    c => vec![c],
+                })
+                .take(width)
+                .collect();
+            lines.push(format!("  {}  \"{}   {}", kind, name, content));
+        }
+        self.message = lines.join("\n");
+    }
+
+    /// runs the command line, returns true when the editor should exit
+    fn run_command(&mut self) -> bool {
+        let cmd = std::mem::take(&mut self.cmd);
+        self.cmdix = 0;
+        if !cmd.trim().is_empty() {
+            self.registers.set_command(&cmd);
+        }
+        match self.execute(&cmd) {
+            Ok(quit) => quit,
+            Err(e) => {
+                self.message = e;
+                self.message_is_error = true;
+                self.stop_replay();
+                false
+            }
+        }
+    }
+
+    fn execute(&mut self, cmd: &str) -> Result<bool, String> {
+        let cmd = cmd.trim();
+        let end = cmd
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(cmd.len());
+        let (name, rest) = cmd.split_at(end);
+        let (bang, arg) = match rest.strip_prefix('!') {
+            Some(arg) => (true, arg.trim()),
+            None => (false, rest.trim()),
+        };
+        match name {
+            "q" | "quit" => return self.quit(bang),
+            "w" | "write" => self.write(arg, bang)?,
+            "wq" => {
+                self.write(arg, bang)?;
+                return self.quit(bang);
+            }
+            "x" | "xit" | "exit" => {
+                if self.current().1.modified() {
+                    self.write(arg, bang)?;
+                }
+                return self.quit(bang);
+            }
+            "qa" | "qall" | "quita" | "quitall" => return self.quit_all(bang),
+            "wa" | "wall" => self.write_all(bang)?,
+            "wqa" | "wqall" | "xa" | "xall" => {
+                self.write_all(bang)?;
+                return self.quit_all(bang);
+            }
+            "e" | "edit" => self.edit(arg, bang)?,
+            "sp" | "split" => self.split(arg)?,
+            "wincmd" => return self.wincmd(arg),
+            "se" | "set" => self.set(arg)?,
+            "st" | "stop" | "sus" | "suspend" => self.suspend(),
+            "reg" | "registers" | "di" | "display" => self.list_registers(arg),
+            "ea" | "earlier" => self.time_travel(arg, false)?,
+            "lat" | "later" => self.time_travel(arg, true)?,
+            "" => {}
+            _ => return Err(format!("E492: Not an editor command: {}", cmd)),
+        }
+        Ok(false)
+    }
+
+    pub fn run(&mut self) -> Result<(), Errno> {
+        self.term.enter_raw()?;
+        self.term.set_mouse(self.options.mouse);
+        self.set_size();
+        loop {
+            if !self.replay.is_empty() && self.interrupted()? {
+                self.stop_replay();
+                self.message = "Interrupted".to_string();
+                self.message_is_error = true;
+                continue;
+            }
+            // keys of a macro are handled without drawing in between
+            let (key, typed) = match self.replay.pop_front() {
+                Some(key) => (key, false),
+                None => {
+                    self.refresh_screen()?;
+                    let Some(key) = self.read_editor_key()? else {
+                        continue;
+                    };
+                    (key, true)
+                }
+            };
+            let record = typed && self.recording.is_some();
+            self.message.clear();
+            self.message_is_error = false;
+            if self.process_key(key.clone()) {
+                self.term.leave_raw();
+                return Ok(());
+            }
+            self.scroll_windows();
+            // the `q` that ends the recording isn't part of it
+            if let Some((_, keys)) = self.recording.as_mut().filter(|_| record) {
+                keys.push(key);
+            }
+        }
+    }
+
+    /// acts on one key, returns true when the editor should exit. the
+    /// edits a key makes are undone together, those of an insert once it
+    /// ends
+    fn process_key(&mut self, key: EditorKey) -> bool {
+        let (win, buf) = self.current();
+        buf.set_undo_cursor(win.cy, win.cx);
+        let quit = self.handle_key(key);
+        if !self.mode.is_insert() {
+            for buf in &mut self.buffers {
+                buf.commit_undo();
+            }
+        }
+        quit
+    }
+
+    fn handle_key(&mut self, key: EditorKey) -> bool {
+        let in_text = self.mode != EditorMode::Command;
+        let editing = self.mode == EditorMode::Normal || self.mode.is_insert();
+        if self.mode.is_insert()
+            && matches!(
+                key,
+                EditorKey::ArrowLeft
+                    | EditorKey::ArrowRight
+                    | EditorKey::ArrowUp
+                    | EditorKey::ArrowDown
+                    | EditorKey::HomeKey
+                    | EditorKey::EndKey
+                    | EditorKey::PageUp
+                    | EditorKey::PageDown
+                    | EditorKey::DelKey
+                    | EditorKey::Mouse(_)
+            )
+        {
+            // moving around starts a new change
+            self.insertion.moved();
+            self.current().1.commit_undo();
+        }
+        if let Some(c) = normal::key_as_normal(&key) {
+            if self.mode == EditorMode::Normal {
+                return self.normal_key(c);
+            } else if self.mode.is_visual() {
+                return self.visual_key(c);
+            }
+        }
+        match key {
+            // pressed again it switches between inserting and replacing
+            EditorKey::Insert => match self.mode {
+                EditorMode::Normal => self.start_insert("i", 1),
+                EditorMode::Insert => self.mode = EditorMode::Replace,
+                EditorMode::Replace | EditorMode::VirtualReplace => self.mode = EditorMode::Insert,
+                _ => {}
+            },
+            EditorKey::ArrowLeft => {
+                if in_text {
+                    self.current().0.move_left();
+                } else if self.cmdix != 0 {
+                    self.cmdix -= 1;
+                }
+            }
+            EditorKey::ArrowRight => {
+                if in_text {
+                    let (win, buf) = self.current();
+                    win.move_right(buf);
+                } else if self.cmdix != self.cmd.chars().count() {
+                    self.cmdix += 1;
+                }
+            }
+            EditorKey::ArrowUp if in_text => {
+                let (win, buf) = self.current();
+                win.move_up(buf);
+            }
+            EditorKey::ArrowDown if in_text => {
+                let (win, buf) = self.current();
+                win.move_down(buf);
+            }
+            EditorKey::DelKey if editing => {
+                let (win, buf) = self.current();
+                buf.remove_char(win.cy, win.cx);
+            }
+            EditorKey::HomeKey => {
+                self.current().0.home();
+            }
+            EditorKey::EndKey => {
+                let (win, buf) = self.current();
+                win.end(buf);
+            }
+            EditorKey::PageUp => {
+                let (win, buf) = self.current();
+                win.page_up(1, buf);
+            }
+            EditorKey::PageDown => {
+                let (win, buf) = self.current();
+                win.page_down(1, buf);
+            }
+            EditorKey::Backspace => {
+                if matches!(self.mode, EditorMode::Replace | EditorMode::VirtualReplace) {
+                    self.replace_backspace();
+                } else if editing {
+                    let insert = self.mode == EditorMode::Insert;
+                    if insert {
+                        self.insertion.backspace();
+                    }
+                    let (win, buf) = self.current();
+                    if win.cx > 0 {
+                        buf.remove_char(win.cy, win.cx - 1);
+                        win.cursor_to(win.cy, win.cx - 1);
+                    } else if win.cy > 0 && insert {
+                        let len = buf.line_len(win.cy - 1);
+                        buf.join_lines(win.cy - 1);
+                        win.cursor_to(win.cy - 1, len);
+                    }
+                } else if in_text {
+                    self.current().0.move_left();
+                } else {
+                    self.cmd_backspace();
+                }
+            }
+            EditorKey::Char(c) => match self.mode {
+                EditorMode::Normal => return self.normal_key(c),
+                EditorMode::Insert => self.insert_char(c),
+                EditorMode::Replace | EditorMode::VirtualReplace => self.replace_char(c),
+                EditorMode::Command => self.cmd_insert(c),
+                _ => return self.visual_key(c),
+            },
+            EditorKey::Paste(text) => match self.mode {
+                EditorMode::Normal => self.insert_text(&text),
+                EditorMode::Insert => {
+                    self.insertion.typed(&text);
+                    self.insert_text(&text);
+                }
+                EditorMode::Replace | EditorMode::VirtualReplace => {
+                    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                    for c in text.chars() {
+                        self.replace_char(c);
+                    }
+                }
+                EditorMode::Command => {
+                    for c in text.chars().filter(|c| !c.is_control()) {
+                        self.cmd_insert(c);
+                    }
+                }
+                _ => {}
+            },
+            EditorKey::Mouse(ev) => self.mouse(ev),
+            EditorKey::Mod(mods, key) if mods.contains(Modifiers::CTRL) && in_text => match *key {
+                EditorKey::HomeKey => self.current().0.cursor_to(0, 0),
+                EditorKey::EndKey => {
+                    let (win, buf) = self.current();
+                    let last = buf.last_line();
+                    win.cursor_to(last, buf.line_len(last));
+                }
+                key => return self.process_key(key),
+            },
+            // anything else does what the plain key does
+            EditorKey::Mod(_, key) => return self.process_key(*key),
+            EditorKey::K(c) => match self.mode {
+                EditorMode::Normal => return self.normal_key(c as char),
+                EditorMode::Insert => match c {
+                    b'\x1b' => self.finish_insert(),
+                    b'\r' => {
+                        self.insertion.typed("\n");
+                        let (win, buf) = self.current();
+                        buf.split_line(win.cy, win.cx);
+                        win.cursor_to(win.cy + 1, 0);
+                    }
+                    _ => {
+                        if c > 31 && c < 127 {
+                            self.insert_char(c as char);
+                        }
+                    }
+                },
+                EditorMode::Replace | EditorMode::VirtualReplace => match c {
+                    b'\x1b' => self.finish_insert(),
+                    b'\r' => self.replace_char('\n'),
+                    b'\t' => self.replace_char('\t'),
+                    _ => {
+                        if c > 31 && c < 127 {
+                            self.replace_char(c as char);
+                        }
+                    }
+                },
+                EditorMode::Visual | EditorMode::VisualLine | EditorMode::VisualBlock => {
+                    return self.visual_key(c as char);
+                }
+                EditorMode::Command => match c {
+                    b'\x1b' => {
+                        self.mode = EditorMode::Normal;
+                    }
+                    b'\r' => {
+                        self.mode = EditorMode::Normal;
+                        return self.run_command();
+                    }
+                    _ => {
+                        if c > 31 && c < 127 {
+                            self.cmd_insert(c as char);
+                        }
+                    }
+                },
+            },
+            _ => {}
+        }
+        false
+    }
+
+    /// the visual selection in the current window
+    fn selection(&self) -> Option<Selection> {
+        let win = &self.windows[self.current];
+        let kind = match self.mode {
+            EditorMode::Visual => Kind::Charwise,
+            EditorMode::VisualLine => Kind::Linewise,
+            EditorMode::VisualBlock => Kind::Blockwise,
+            _ => return None,
+        };
+        Some(Selection::new(
+            win.anchor?,
+            (win.cy, win.cx),
+            kind,
+            win.rightted,
+        ))
+    }
+
+    fn leave_visual(&mut self) {
+        let win = &mut self.windows[self.current];
+        if let Some(anchor) = win.anchor.take() {
+            self.last_visual = Some(visual::Visual {
+                mode: self.mode,
+                anchor,
+                cursor: (win.cy, win.cx),
+                to_eol: win.rightted,
+            });
+        }
+        self.mode = EditorMode::Normal;
+    }
+
+    /// index of the window shown at the 0 based screen `row`, counting its
+    /// status line as part of it
+    fn window_at(&self, row: usize) -> Option<usize> {
+        let status = self.status_rows();
+        self.windows
+            .iter()
+            .position(|win| row >= win.top && row < win.top + win.height + status)
+    }
+
+    /// clicking puts the cursor where the mouse is, in the line numbers
+    /// it selects the whole line. dragging selects and the wheel scrolls
+    fn mouse(&mut self, ev: MouseEvent) {
+        if !self.options.mouse || self.mode == EditorMode::Command {
+            return;
+        }
+        match ev.kind {
+            MouseKind::Press(MouseButton::Left) => {
+                let Some(ix) = self.window_at(ev.row) else {
+                    return;
+                };
+                if self.mode.is_visual() {
+                    self.leave_visual();
+                }
+                self.current = ix;
+                let (win, buf) = self.current();
+                if ev.row >= win.top + win.height {
+                    // the status line only focuses the window
+                    return;
+                }
+                let (line, col) = win.screen_to_pos(buf, ev.row, ev.col);
+                if win.in_gutter(ev.col) {
+                    win.cursor_to(line, 0);
+                    win.anchor = Some((line, 0));
+                    self.mode = EditorMode::VisualLine;
+                } else {
+                    win.cursor_to(line, col);
+                }
+            }
+            MouseKind::Drag(MouseButton::Left) => {
+                if self.mode == EditorMode::Normal || self.mode.is_insert() {
+                    let win = &mut self.windows[self.current];
+                    win.anchor = Some((win.cy, win.cx));
+                    self.mode = EditorMode::Visual;
+                }
+                let (win, buf) = self.current();
+                // dragging past the window scrolls it
+                let row = ev.row.clamp(win.top, win.top + win.height - 1);
+                if ev.row < win.top {
+                    win.scroll_by(-1, buf);
+                } else if ev.row >= win.top + win.height {
+                    win.scroll_by(1, buf);
+                }
+                let (line, col) = win.screen_to_pos(buf, row, ev.col);
+                win.cursor_to(line, col);
+            }
+            MouseKind::ScrollUp | MouseKind::ScrollDown => {
+                let Some(ix) = self.window_at(ev.row) else {
+                    return;
+                };
+                let delta = if ev.kind == MouseKind::ScrollUp {
+                    -MOUSE_SCROLL_LINES
+                } else {
+                    MOUSE_SCROLL_LINES
+                };
+                let win = &mut self.windows[ix];
+                win.scroll_by(delta, &self.buffers[win.buf]);
+            }
+            _ => {}
+        }
+    }
+
+    /// types `c` at the cursor in insert mode
+    fn insert_char(&mut self, c: char) {
+        self.insertion.typed(c.encode_utf8(&mut [0; 4]));
+        let (win, buf) = self.current();
+        buf.insert_char(win.cy, win.cx, c);
+        win.cursor_to(win.cy, win.cx + 1);
+    }
+
+    /// inserts `text` at the cursor as a single change and puts the cursor
+    /// after it, terminals send line breaks in pastes as '\r'
+    fn insert_text(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let (win, buf) = self.current();
+        let start = buf.pos_to_char(win.cy, win.cx);
+        buf.insert(start, &text);
+        let (line, col) = buf.char_to_pos(start + text.chars().count());
+        win.cursor_to(line, col);
+    }
+
+    /// byte offset in `cmd` of the char at `cmdix`
+    fn cmd_offset(&self, ix: usize) -> usize {
+        self.cmd
+            .char_indices()
+            .nth(ix)
+            .map_or(self.cmd.len(), |(offset, _)| offset)
+    }
+
+    fn cmd_insert(&mut self, c: char) {
+        let offset = self.cmd_offset(self.cmdix);
+        self.cmd.insert(offset, c);
+        self.cmdix += 1;
+    }
+
+    fn cmd_backspace(&mut self) {
+        if self.cmdix != 0 {
+            let offset = self.cmd_offset(self.cmdix - 1);
+            self.cmd.remove(offset);
+            self.cmdix -= 1;
+        }
+    }
+}
+
+/// the message vim shows after a write, like `"file" 3L, 12B written`
+fn written_message(name: &str, buffer: &Buffer, bytes: usize) -> String {
+    let mut lines = buffer.line_count();
+    if buffer.line_len(lines - 1) == 0 {
+        lines -= 1;
+    }
+    format!("\"{}\" {}L, {}B written", name, lines, bytes)
+}
+
+/// the buffer name with a `[+]` when it has unsaved changes
+fn status_name(buffer: &Buffer) -> String {
+    if buffer.modified() {
+        format!("{} [+]", buffer.name())
+    } else {
+        buffer.name()
+    }
+}
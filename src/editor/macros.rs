@@ -0,0 +1,64 @@
+use super::Editor;
+use crate::register::Register;
+
+impl Editor {
+    /// `q{name}`, the keys typed from here on until the next `q` go into
+    /// register `name`. false when it isn't one that can be recorded into
+    pub(super) fn start_recording(&mut self, name: char) -> bool {
+        if !name.is_ascii_alphanumeric() && name != '"' {
+            return false;
+        }
+        self.recording = Some((name, Vec::new()));
+        true
+    }
+
+    /// `q` while recording, what was typed is put into the register
+    pub(super) fn stop_recording(&mut self) {
+        if let Some((name, keys)) = self.recording.take() {
+            self.registers.set(name, Register::recorded(keys));
+        }
+    }
+
+    /// `{count}@{name}`, the keys in register `name` are handled `count`
+    /// times as if they were typed. `@@` uses the register used last and
+    /// `@:` runs the last command line again. false when there is nothing
+    /// to run
+    pub(super) fn run_macro(&mut self, name: char, count: usize) -> bool {
+        let name = match (name, self.last_macro) {
+            ('@', Some(last)) => last,
+            ('@', None) => {
+                self.message = "E748: No previously used register".to_string();
+                self.message_is_error = true;
+                return false;
+            }
+            _ => name,
+        };
+        let register = if name == ':' {
+            let Some(cmd) = self.registers.get(':') else {
+                self.message = "E30: No previous command line".to_string();
+                self.message_is_error = true;
+                return false;
+            };
+            Register::new(format!(":{}\r", cmd.text), cmd.kind)
+        } else {
+            match self.register(Some(name)) {
+                Some(register) => register,
+                None => return false,
+            }
+        };
+        self.last_macro = Some(name);
+        let keys = register.keys();
+        for _ in 0..count {
+            for key in keys.iter().rev() {
+                self.replay.push_front(key.clone());
+            }
+        }
+        true
+    }
+
+    /// a command failed, the keys of the macros being run are dropped
+    /// so that a recursive macro ends there
+    pub(super) fn stop_replay(&mut self) {
+        self.replay.clear();
+    }
+}
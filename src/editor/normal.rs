@@ -0,0 +1,365 @@
+use std::ops::RangeInclusive;
+
+use super::repeat::Change;
+use super::{Editor, EditorMode};
+use crate::input::EditorKey;
+use crate::motion::{first_non_blank, Motion};
+use crate::normal::{Command, Step};
+use crate::window::ScreenPos;
+
+/// text typed since insert mode was entered, repeated on Esc when the
+/// insert command had a count
+#[derive(Debug, Default)]
+pub(super) struct Insertion {
+    count: usize,
+    /// put before each repetition, a line break for `o` and `O`
+    prefix: &'static str,
+    text: String,
+    /// for a block insert or change, what was typed goes into these lines
+    /// as well
+    block: Option<BlockInsert>,
+    /// in replace mode, the text each typed char took the place of, for
+    /// backspace to put back
+    replaced: Vec<String>,
+    /// the command that started the insert, `.` makes it again together
+    /// with what was typed
+    pub(super) cmd: Option<Command>,
+}
+
+/// the lines below the first one of a block insert, and where in them
+#[derive(Debug)]
+struct BlockInsert {
+    lines: RangeInclusive<usize>,
+    /// the column to insert at, the end of each line when None
+    col: Option<usize>,
+    /// lines too short to reach `col` are filled up with spaces instead of
+    /// being skipped
+    pad: bool,
+}
+
+impl Insertion {
+    pub(super) fn typed(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    /// backspace in insert mode, repeating stops making sense once it
+    /// deletes text from before the insert
+    pub(super) fn backspace(&mut self) {
+        if self.text.pop().is_none() {
+            self.count = 1;
+        }
+    }
+
+    /// the cursor moved elsewhere, what was typed is no longer repeated
+    /// and `.` only inserts what gets typed from here on
+    pub(super) fn moved(&mut self) {
+        self.count = 1;
+        self.block = None;
+        self.replaced.clear();
+        self.text.clear();
+        if let Some(cmd) = &mut self.cmd {
+            if !matches!(cmd.keys.as_str(), "R" | "gR") {
+                cmd.keys = "i".to_string();
+            }
+            cmd.operator = None;
+            cmd.count = None;
+        }
+    }
+
+    /// a char typed in replace mode took the place of `text`
+    pub(super) fn replaced(&mut self, text: String) {
+        self.replaced.push(text);
+    }
+
+    /// what the last char typed in replace mode took the place of, None
+    /// when nothing was typed since replace mode was entered
+    pub(super) fn unreplace(&mut self) -> Option<String> {
+        self.replaced.pop()
+    }
+
+    /// what gets typed is put into `lines` at `col` as well, or at their
+    /// ends when `col` is None
+    pub(super) fn block(&mut self, lines: RangeInclusive<usize>, col: Option<usize>, pad: bool) {
+        self.block = Some(BlockInsert { lines, col, pad });
+    }
+}
+
+/// the normal mode command a special key stands for
+pub(super) fn key_as_normal(key: &EditorKey) -> Option<char> {
+    Some(match key {
+        EditorKey::ArrowLeft => 'h',
+        EditorKey::ArrowRight => 'l',
+        EditorKey::ArrowUp => 'k',
+        EditorKey::ArrowDown => 'j',
+        EditorKey::HomeKey => '0',
+        EditorKey::EndKey => '$',
+        EditorKey::Backspace => '\x08',
+        // Ctrl-B and Ctrl-F
+        EditorKey::PageUp => '\x02',
+        EditorKey::PageDown => '\x06',
+        EditorKey::DelKey => 'x',
+        _ => return None,
+    })
+}
+
+impl Editor {
+    /// feeds a key to the normal mode parser and runs the command once it
+    /// is complete, returns true when the editor should exit
+    pub(super) fn normal_key(&mut self, c: char) -> bool {
+        self.clamp_cursor();
+        if c == '\x1b' {
+            self.pending.clear();
+            return false;
+        }
+        if c == 'q' && self.recording.is_some() && self.pending.is_empty() {
+            self.stop_recording();
+            return false;
+        }
+        let quit = match self.pending.push(c) {
+            Step::Pending => false,
+            Step::Invalid => {
+                self.stop_replay();
+                false
+            }
+            Step::Done(cmd) => self.normal_command(cmd),
+        };
+        self.clamp_cursor();
+        quit
+    }
+
+    /// outside of insert mode the cursor stays on the text, while still
+    /// remembering the column it would like to be in
+    pub(super) fn clamp_cursor(&mut self) {
+        if self.mode == EditorMode::Normal {
+            let (win, buf) = self.current();
+            win.cx = win.cx.min(buf.line_len(win.cy).saturating_sub(1));
+        }
+    }
+
+    /// runs a command and keeps it for `.` when it changed the text
+    pub(super) fn normal_command(&mut self, cmd: Command) -> bool {
+        let changenr = self.current().1.changenr();
+        let keep = !matches!(cmd.keys.as_str(), "." | "u" | "\x12" | "U" | "g-" | "g+");
+        let quit = self.run_normal(cmd.clone());
+        if keep {
+            self.keep_change(cmd, changenr);
+        }
+        quit
+    }
+
+    fn run_normal(&mut self, cmd: Command) -> bool {
+        let cmd = Self::expand_shortcut(cmd);
+        let count = cmd.count1();
+        let ok = if cmd.operator.is_some() {
+            self.operator_command(&cmd)
+        } else if let Some(motion) = self.motion(&cmd.keys) {
+            self.move_cursor(motion, cmd.count)
+        } else if self.view_command(&cmd) {
+            true
+        } else {
+            self.simple_command(&cmd, count)
+        };
+        // like a typed command failing beeps, one in a macro ends it
+        if !ok {
+            self.stop_replay();
+        }
+        false
+    }
+
+    /// the commands without an operator or motion, false when one failed
+    fn simple_command(&mut self, cmd: &Command, count: usize) -> bool {
+        match cmd.keys.as_str() {
+            "i" | "a" | "I" | "A" | "o" | "O" => self.start_insert(&cmd.keys, count),
+            "R" => self.start_replace(false, count),
+            "gR" => self.start_replace(true, count),
+            "v" => self.start_visual(EditorMode::Visual),
+            "V" => self.start_visual(EditorMode::VisualLine),
+            "\x16" => self.start_visual(EditorMode::VisualBlock),
+            "gv" => return self.reselect(),
+            "p" | "P" => return self.put(cmd.keys == "P", count, cmd.register),
+            "J" => return self.join(count, true),
+            "gJ" => return self.join(count, false),
+            "~" => return self.toggle_case(count),
+            keys if keys.starts_with('r') => {
+                let Some(c) = keys.chars().nth(1) else {
+                    return false;
+                };
+                return self.replace_chars(c, count);
+            }
+            keys if keys.starts_with("gr") => {
+                let Some(c) = keys.chars().nth(2) else {
+                    return false;
+                };
+                return self.virtual_replace_chars(c, count);
+            }
+            keys if keys.starts_with('q') => {
+                let Some(name) = keys.chars().nth(1) else {
+                    return false;
+                };
+                return self.start_recording(name);
+            }
+            keys if keys.starts_with('@') => {
+                let Some(name) = keys.chars().nth(1) else {
+                    return false;
+                };
+                return self.run_macro(name, count);
+            }
+            "u" => self.undo(count),
+            "\x12" => self.redo(count),
+            "U" => return self.undo_line(),
+            "g-" => self.undo_steps(count, false),
+            "g+" => self.undo_steps(count, true),
+            "." => return self.repeat_change(cmd.count),
+            ":" => self.mode = EditorMode::Command,
+            _ => {}
+        }
+        true
+    }
+
+    /// commands that scroll or suspend, which work the same in normal and
+    /// visual mode. false when `cmd` isn't one of them
+    pub(super) fn view_command(&mut self, cmd: &Command) -> bool {
+        let count = cmd.count1();
+        let (win, buf) = self.current();
+        match cmd.keys.as_str() {
+            // Ctrl-E and Ctrl-Y
+            "\x05" => win.scroll_by(count as isize, buf),
+            "\x19" => win.scroll_by(-(count as isize), buf),
+            // Ctrl-D and Ctrl-U
+            "\x04" => _ = win.scroll_half(true, cmd.count, buf),
+            "\x15" => _ = win.scroll_half(false, cmd.count, buf),
+            // Ctrl-F and Ctrl-B
+            "\x06" => _ = win.page_down(count, buf),
+            "\x02" => _ = win.page_up(count, buf),
+            "zt" | "z\r" | "zz" | "z." | "zb" | "z-" => {
+                if let Some(line) = cmd.count {
+                    win.cy = (line - 1).min(buf.last_line());
+                    win.set_x_after_up_down(buf);
+                }
+                if matches!(cmd.keys.as_str(), "z\r" | "z." | "z-") {
+                    win.cursor_to(win.cy, first_non_blank(buf, win.cy));
+                }
+                win.scroll_cursor_to(match &cmd.keys[1..] {
+                    "t" | "\r" => ScreenPos::Top,
+                    "z" | "." => ScreenPos::Middle,
+                    _ => ScreenPos::Bottom,
+                });
+            }
+            // Ctrl-Z
+            "\x1a" => self.suspend(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// the motion typed as `keys`, finds are remembered for `;` and `,`
+    pub(super) fn motion(&mut self, keys: &str) -> Option<Motion> {
+        match keys {
+            ";" => Some(Motion::Find(self.last_find?.repeat(false))),
+            "," => Some(Motion::Find(self.last_find?.repeat(true))),
+            _ => {
+                let motion = Motion::parse(keys)?;
+                if let Motion::Find(find) = motion {
+                    self.last_find = Some(find);
+                }
+                Some(motion)
+            }
+        }
+    }
+
+    /// moves the cursor of the current window, false when the motion
+    /// failed
+    pub(super) fn move_cursor(&mut self, motion: Motion, count: Option<usize>) -> bool {
+        let (win, buf) = self.current();
+        match motion.target(win, buf, count, false) {
+            Some(target) => {
+                target.apply(win, buf);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// `i`, `a`, `I`, `A`, `o` and `O`, what gets typed is inserted
+    /// `count` times
+    pub(super) fn start_insert(&mut self, how: &str, count: usize) {
+        let (win, buf) = self.current();
+        let len = buf.line_len(win.cy);
+        match how {
+            "a" => win.cursor_to(win.cy, (win.cx + 1).min(len)),
+            "I" => {
+                let line = buf.line(win.cy);
+                let indent = line.chars().take_while(|c| c.is_whitespace()).count();
+                win.cursor_to(win.cy, indent);
+            }
+            "A" => win.cursor_to(win.cy, len),
+            "o" => {
+                buf.split_line(win.cy, len);
+                win.cursor_to(win.cy + 1, 0);
+            }
+            "O" => {
+                buf.split_line(win.cy, 0);
+                win.cursor_to(win.cy, 0);
+            }
+            _ => {}
+        }
+        self.insertion = Insertion {
+            count,
+            prefix: if matches!(how, "o" | "O") { "\n" } else { "" },
+            text: String::new(),
+            block: None,
+            replaced: Vec::new(),
+            cmd: None,
+        };
+        self.mode = EditorMode::Insert;
+    }
+
+    /// Esc in insert mode, the cursor goes back onto the last char typed
+    pub(super) fn finish_insert(&mut self) {
+        let insertion = std::mem::take(&mut self.insertion);
+        self.registers.set_inserted(&insertion.text);
+        if let Some(cmd) = insertion.cmd.clone() {
+            self.last_change = Some(Change::new(cmd, insertion.text.clone()));
+        }
+        if insertion.count > 1 && !insertion.text.is_empty() {
+            let once = format!("{}{}", insertion.prefix, insertion.text);
+            let again = once.repeat(insertion.count - 1);
+            if self.mode == EditorMode::Insert {
+                self.insert_text(&again);
+            } else {
+                // replacing goes on over the text after what was replaced
+                let virt = self.mode == EditorMode::VirtualReplace;
+                for c in again.chars() {
+                    self.overwrite(c, virt);
+                }
+            }
+        }
+        self.mode = EditorMode::Normal;
+        let win = &mut self.windows[self.current];
+        win.cursor_to(win.cy, win.cx.saturating_sub(1));
+        if let Some(block) = insertion.block {
+            let text = insertion.text.repeat(insertion.count);
+            if text.contains('\n') {
+                return;
+            }
+            let (win, buf) = self.current();
+            for line in block.lines {
+                let len = buf.line_len(line);
+                let at = match block.col {
+                    None => len,
+                    Some(col) if len >= col => col,
+                    Some(col) if block.pad => {
+                        buf.insert(buf.pos_to_char(line, len), &" ".repeat(col - len));
+                        col
+                    }
+                    // lines too short to reach the block are skipped
+                    Some(_) => continue,
+                };
+                buf.insert(buf.pos_to_char(line, at), &text);
+            }
+            if let Some(col) = block.col {
+                win.cursor_to(win.cy, col);
+            }
+        }
+    }
+}
@@ -0,0 +1,763 @@
+use super::Editor;
+use crate::buffer::Buffer;
+use crate::clipboard;
+use crate::motion::{first_non_blank, Col, Motion, Target};
+use crate::normal::Command;
+use crate::options::Options;
+use crate::register::{Kind, Register, Registers};
+use crate::textobject;
+
+/// the text an operator works on
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(super) enum Region {
+    /// chars `start..end` of the buffer
+    Chars { start: usize, end: usize },
+    /// lines `first..=last`
+    Lines { first: usize, last: usize },
+    /// columns `left..right` of lines `top..=bottom`, up to the end of
+    /// every line when `right` is None
+    Block {
+        top: usize,
+        bottom: usize,
+        left: usize,
+        right: Option<usize>,
+    },
+}
+
+impl Region {
+    /// the first and last line touched
+    pub(super) fn lines(&self, buf: &Buffer) -> (usize, usize) {
+        match *self {
+            Region::Chars { start, end } => (
+                buf.char_to_line(start),
+                buf.char_to_line(end.max(start + 1) - 1),
+            ),
+            Region::Lines { first, last } => (first, last),
+            Region::Block { top, bottom, .. } => (top, bottom),
+        }
+    }
+
+    /// char range of the block's part of `line`, empty when the line
+    /// doesn't reach the block
+    fn block_range(buf: &Buffer, line: usize, left: usize, right: Option<usize>) -> (usize, usize) {
+        let len = buf.line_len(line);
+        let start = buf.line_to_char(line);
+        let right = right.map_or(len, |right| right.min(len));
+        (start + left.min(len), start + right.max(left.min(len)))
+    }
+
+    /// the text of the region as it goes into a register
+    fn yank(&self, buf: &Buffer) -> Register {
+        match *self {
+            Region::Chars { start, end } => {
+                Register::new(buf.slice(start..end).to_string(), Kind::Charwise)
+            }
+            Region::Lines { first, last } => {
+                let end = if last + 1 < buf.line_count() {
+                    buf.line_to_char(last + 1)
+                } else {
+                    buf.len_chars()
+                };
+                let mut text = buf.slice(buf.line_to_char(first)..end).to_string();
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                Register::new(text, Kind::Linewise)
+            }
+            Region::Block {
+                top,
+                bottom,
+                left,
+                right,
+            } => {
+                let lines: Vec<String> = (top..=bottom)
+                    .map(|line| {
+                        let (start, end) = Self::block_range(buf, line, left, right);
+                        buf.slice(start..end).to_string()
+                    })
+                    .collect();
+                Register::new(lines.join("\n"), Kind::Blockwise)
+            }
+        }
+    }
+}
+
+/// commands that are short for an operator and a motion
+fn shortcut(keys: &str) -> Option<(&'static str, &'static str)> {
+    Some(match keys {
+        "x" => ("d", "l"),
+        "X" => ("d", "h"),
+        "D" => ("d", "$"),
+        "C" => ("c", "$"),
+        "s" => ("c", "l"),
+        "S" => ("c", "c"),
+        "Y" => ("y", "y"),
+        _ => return None,
+    })
+}
+
+/// the columns a run of leading blanks takes up
+fn indent_width(indent: &str, tabstop: usize) -> usize {
+    indent.chars().fold(0, |width, c| match c {
+        '\t' => (width / tabstop + 1) * tabstop,
+        _ => width + 1,
+    })
+}
+
+/// blanks taking up `width` columns, tabs as far as 'expandtab' allows
+fn make_indent(width: usize, options: &Options) -> String {
+    let tabstop = options.tabstop as usize;
+    if options.expandtab {
+        " ".repeat(width)
+    } else {
+        "\t".repeat(width / tabstop) + &" ".repeat(width % tabstop)
+    }
+}
+
+/// the columns one `>>` shifts by, 'tabstop' when 'shiftwidth' is 0
+fn shiftwidth(options: &Options) -> usize {
+    match options.shiftwidth {
+        0 => options.tabstop as usize,
+        sw => sw as usize,
+    }
+}
+
+fn rot13(c: char) -> char {
+    match c {
+        'a'..='z' => (((c as u8 - b'a') + 13) % 26 + b'a') as char,
+        'A'..='Z' => (((c as u8 - b'A') + 13) % 26 + b'A') as char,
+        _ => c,
+    }
+}
+
+/// `c` changed the way a case operator does, chars whose case takes
+/// more than one char stay as they are
+fn change_case(op: &str, c: char) -> char {
+    match op {
+        "gu" => single(c.to_lowercase(), c),
+        "gU" => single(c.to_uppercase(), c),
+        "g?" => rot13(c),
+        _ if c.is_lowercase() => single(c.to_uppercase(), c),
+        _ => single(c.to_lowercase(), c),
+    }
+}
+
+/// the only char of `chars`, or `c` when there are more
+fn single(mut chars: impl Iterator<Item = char>, c: char) -> char {
+    match (chars.next(), chars.next()) {
+        (Some(first), None) => first,
+        _ => c,
+    }
+}
+
+/// the indent `=` gives a line after one with `prev` columns of indent
+/// ending in `prev_end`, a brace opened before indents and one closed
+/// first dedents
+fn reindent(prev: usize, prev_end: Option<char>, line: &str, sw: usize) -> usize {
+    let mut width = prev;
+    if matches!(prev_end, Some('{' | '(' | '[')) {
+        width += sw;
+    }
+    if line.starts_with(['}', ')', ']']) {
+        width = width.saturating_sub(sw);
+    }
+    width
+}
+
+impl Editor {
+    /// runs a command that has an operator, false when its motion fails
+    pub(super) fn operator_command(&mut self, cmd: &Command) -> bool {
+        let op = cmd.operator.as_deref().unwrap_or_default();
+        match self.operator_region(cmd) {
+            Some(region) => self.apply_operator(op, region, cmd.register),
+            None => false,
+        }
+    }
+
+    /// the shortcut `keys` stands for, as an operator command
+    pub(super) fn expand_shortcut(cmd: Command) -> Command {
+        match shortcut(&cmd.keys) {
+            Some((op, keys)) if cmd.operator.is_none() => Command {
+                operator: Some(op.to_string()),
+                keys: keys.to_string(),
+                ..cmd
+            },
+            _ => cmd,
+        }
+    }
+
+    /// what the operator of `cmd` works on, from the cursor to where its
+    /// motion goes
+    fn operator_region(&mut self, cmd: &Command) -> Option<Region> {
+        let op = cmd.operator.as_deref()?;
+        if cmd.keys == op {
+            let (win, buf) = self.current();
+            let last = buf.last_line();
+            let count = cmd.count1();
+            if count > 1 && win.cy == last {
+                return None;
+            }
+            return Some(Region::Lines {
+                first: win.cy,
+                last: (win.cy + count - 1).min(last),
+            });
+        }
+        if let Some(object) = self.text_object(&cmd.keys, cmd.count1()) {
+            return Some(object);
+        }
+        let mut motion = self.motion(&cmd.keys)?;
+        let (win, buf) = self.current();
+        if let (Motion::WordStart(big), "c") = (motion, op) {
+            // `cw` on a word changes just the word, like `ce`
+            if buf
+                .char_at(win.cy, win.cx)
+                .is_some_and(|c| !c.is_whitespace())
+            {
+                motion = Motion::ChangeWord(big);
+            }
+        }
+        let target = motion.target(win, buf, cmd.count, true)?;
+        Some(self.motion_region(target, cmd.force))
+    }
+
+    /// the region of the text object typed as `keys` around the cursor
+    pub(super) fn text_object(&mut self, keys: &str, count: usize) -> Option<Region> {
+        let (win, buf) = self.current();
+        let at = buf.pos_to_char(win.cy, win.cx);
+        let object = textobject::select(buf, at, keys, count)?;
+        Some(if object.linewise {
+            Region::Lines {
+                first: buf.char_to_line(object.start),
+                last: buf.char_to_line(object.end.max(object.start + 1) - 1),
+            }
+        } else {
+            Region::Chars {
+                start: object.start,
+                end: object.end,
+            }
+        })
+    }
+
+    /// the text between the cursor and `target`, with vim's rules for
+    /// exclusive motions that end at the start of a line
+    fn motion_region(&mut self, target: Target, force: Option<char>) -> Region {
+        let (win, buf) = self.current();
+        let cursor = (win.cy, win.cx);
+        let to = target.pos(win, buf);
+        let (start, mut end) = if to < cursor {
+            (to, cursor)
+        } else {
+            (cursor, to)
+        };
+        let mut linewise = target.linewise;
+        let mut inclusive = target.inclusive;
+        match force {
+            Some('V') => linewise = true,
+            Some('\x16') => {
+                let to_eol = target.col == Col::End || (target.col == Col::Sticky && win.rightted);
+                return Region::Block {
+                    top: start.0,
+                    bottom: end.0,
+                    left: cursor.1.min(to.1),
+                    right: (!to_eol).then(|| cursor.1.max(to.1) + 1),
+                };
+            }
+            Some(_) if linewise => (linewise, inclusive) = (false, false),
+            Some(_) => inclusive = !inclusive,
+            None => {}
+        }
+        if linewise {
+            return Region::Lines {
+                first: start.0,
+                last: end.0,
+            };
+        }
+        if !inclusive && force.is_none() && end.1 == 0 && end.0 > start.0 {
+            if start.1 <= first_non_blank(buf, start.0) {
+                return Region::Lines {
+                    first: start.0,
+                    last: end.0 - 1,
+                };
+            }
+            end = (end.0 - 1, buf.line_len(end.0 - 1));
+        }
+        let from = buf.pos_to_char(start.0, start.1);
+        let mut to = buf.pos_to_char(end.0, end.1);
+        if inclusive {
+            to = (to + 1).min(buf.line_to_char(end.0) + buf.line_len(end.0));
+        }
+        Region::Chars {
+            start: from,
+            end: to,
+        }
+    }
+
+    /// does what `op` does to `region`, false when it can't. yanked or
+    /// deleted text goes into register `reg`
+    pub(super) fn apply_operator(&mut self, op: &str, region: Region, reg: Option<char>) -> bool {
+        if matches!(op, "y" | "d" | "c") && reg.is_some_and(|reg| !Registers::writable(reg)) {
+            return false;
+        }
+        match op {
+            "y" => self.yank(region, reg),
+            "d" => self.delete(region, reg),
+            "c" => self.change(region, reg),
+            "<" | ">" => self.shift(region, op == ">", 1),
+            "=" => self.indent(region),
+            "g~" | "gu" | "gU" | "g?" => self.change_case(op, region),
+            _ => return false,
+        }
+        true
+    }
+
+    /// shows how many lines something happened to, when there are
+    /// more than a few
+    fn report(&mut self, lines: usize, what: &str) {
+        if lines > 2 {
+            self.message = format!("{} {}", lines, what);
+        }
+    }
+
+    fn yank(&mut self, region: Region, reg: Option<char>) {
+        let (win, buf) = self.current();
+        let register = region.yank(buf);
+        let (first, last) = region.lines(buf);
+        match region {
+            Region::Chars { start, .. } => {
+                let (line, col) = buf.char_to_pos(start);
+                win.cursor_to(line, col);
+            }
+            Region::Lines { first, .. } => {
+                win.cy = first;
+                win.set_x_after_up_down(buf);
+            }
+            Region::Block { top, left, .. } => win.cursor_to(top, left),
+        }
+        let lines = last - first + 1;
+        match register.kind {
+            Kind::Linewise => self.report(lines, "lines yanked"),
+            Kind::Blockwise => self.report(lines, &format!("block of {} lines yanked", lines)),
+            Kind::Charwise => {}
+        }
+        self.keep(reg, register, false);
+    }
+
+    pub(super) fn delete(&mut self, region: Region, reg: Option<char>) {
+        let (win, buf) = self.current();
+        let register = region.yank(buf);
+        let lines_before = buf.line_count();
+        match region {
+            Region::Chars { start, end } => {
+                buf.remove(start..end);
+                let (line, col) = buf.char_to_pos(start);
+                win.cursor_to(line, col);
+            }
+            Region::Lines { first, last } => {
+                let mut start = buf.line_to_char(first);
+                let end = if last + 1 < buf.line_count() {
+                    buf.line_to_char(last + 1)
+                } else {
+                    // the last lines take the line break before them along
+                    start = start.saturating_sub(1);
+                    buf.len_chars()
+                };
+                buf.remove(start..end);
+                let line = first.min(buf.last_line());
+                win.cursor_to(line, first_non_blank(buf, line));
+            }
+            Region::Block {
+                top,
+                bottom,
+                left,
+                right,
+            } => {
+                for line in (top..=bottom).rev() {
+                    let (start, end) = Region::block_range(buf, line, left, right);
+                    if start < end {
+                        buf.remove(start..end);
+                    }
+                }
+                win.cursor_to(top, left);
+            }
+        }
+        let fewer = lines_before - buf.line_count();
+        self.report(fewer, "fewer lines");
+        self.keep(reg, register, true);
+    }
+
+    /// deletes the region and starts insert mode where it was, whole
+    /// lines leave an empty line behind and a block gets what is typed
+    /// on each of its lines
+    fn change(&mut self, region: Region, reg: Option<char>) {
+        match region {
+            Region::Lines { first, last } => {
+                let (win, buf) = self.current();
+                let register = region.yank(buf);
+                let start = buf.line_to_char(first);
+                let end = buf.line_to_char(last) + buf.line_len(last);
+                buf.remove(start..end);
+                win.cursor_to(first, 0);
+                self.keep(reg, register, true);
+                self.start_insert("i", 1);
+            }
+            Region::Block {
+                top, bottom, left, ..
+            } => {
+                self.delete(region, reg);
+                self.start_insert("i", 1);
+                self.insertion.block(top + 1..=bottom, Some(left), false);
+            }
+            Region::Chars { .. } => {
+                self.delete(region, reg);
+                self.start_insert("i", 1);
+            }
+        }
+    }
+
+    /// `>` and `<`, lines are shifted by 'shiftwidth' `times` times, empty
+    /// lines are left alone
+    pub(super) fn shift(&mut self, region: Region, right: bool, times: usize) {
+        let sw = shiftwidth(&self.options);
+        let tabstop = self.options.tabstop as usize;
+        let options = self.options.clone();
+        let (win, buf) = self.current();
+        let (first, last) = region.lines(buf);
+        for line in first..=last {
+            let text = buf.line(line).to_string();
+            if text.is_empty() {
+                continue;
+            }
+            let blanks = text.chars().take_while(|c| c.is_whitespace()).count();
+            let indent: String = text.chars().take(blanks).collect();
+            let width = indent_width(&indent, tabstop);
+            let width = if right {
+                width + sw * times
+            } else {
+                width.saturating_sub(sw * times)
+            };
+            set_indent(buf, line, blanks, &make_indent(width, &options));
+        }
+        win.cursor_to(first, first_non_blank(buf, first));
+        let lines = last - first + 1;
+        let plural = if times == 1 { "" } else { "s" };
+        let what = format!(
+            "lines {}ed {} time{}",
+            if right { '>' } else { '<' },
+            times,
+            plural
+        );
+        self.report(lines, &what);
+    }
+
+    /// `=`, each line gets the indent of the line before it, one level
+    /// more after an opening brace and one less before a closing one
+    fn indent(&mut self, region: Region) {
+        let sw = shiftwidth(&self.options);
+        let tabstop = self.options.tabstop as usize;
+        let options = self.options.clone();
+        let (win, buf) = self.current();
+        let (first, last) = region.lines(buf);
+        let mut prev = (0..first)
+            .rev()
+            .map(|l| buf.line(l).to_string())
+            .find(|l| !l.trim().is_empty());
+        for line in first..=last {
+            let text = buf.line(line).to_string();
+            let blanks = text.chars().take_while(|c| c.is_whitespace()).count();
+            let rest = text.trim_start();
+            if rest.is_empty() {
+                set_indent(buf, line, blanks, "");
+                continue;
+            }
+            let width = match &prev {
+                Some(prev) => {
+                    let indent: String = prev.chars().take_while(|c| c.is_whitespace()).collect();
+                    reindent(
+                        indent_width(&indent, tabstop),
+                        prev.trim_end().chars().last(),
+                        rest,
+                        sw,
+                    )
+                }
+                None => 0,
+            };
+            let indent = make_indent(width, &options);
+            set_indent(buf, line, blanks, &indent);
+            prev = Some(format!("{}{}", indent, rest));
+        }
+        win.cursor_to(first, first_non_blank(buf, first));
+        self.report(last - first + 1, "lines indented ");
+    }
+
+    /// `g~`, `gu`, `gU` and `g?`
+    fn change_case(&mut self, op: &str, region: Region) {
+        self.map_chars(region, |c| change_case(op, c));
+        let (win, buf) = self.current();
+        let (first, last) = region.lines(buf);
+        if let Region::Lines { first, .. } = region {
+            win.cursor_to(first, 0);
+        }
+        self.report(last - first + 1, "lines changed");
+    }
+
+    /// replaces each char in `region` with what `f` makes of it, the
+    /// cursor goes to the start
+    pub(super) fn map_chars(&mut self, region: Region, f: impl Fn(char) -> char) {
+        let (win, buf) = self.current();
+        let ranges: Vec<(usize, usize)> = match region {
+            Region::Chars { start, end } => vec![(start, end)],
+            Region::Lines { first, last } => {
+                vec![(
+                    buf.line_to_char(first),
+                    buf.line_to_char(last) + buf.line_len(last),
+                )]
+            }
+            Region::Block {
+                top,
+                bottom,
+                left,
+                right,
+            } => (top..=bottom)
+                .map(|line| Region::block_range(buf, line, left, right))
+                .collect(),
+        };
+        for (start, end) in ranges {
+            let text = buf.slice(start..end).to_string();
+            let changed: String = text.chars().map(&f).collect();
+            if changed != text {
+                buf.remove(start..end);
+                buf.insert(start, &changed);
+            }
+        }
+        match region {
+            Region::Chars { start, .. } => {
+                let (line, col) = buf.char_to_pos(start);
+                win.cursor_to(line, col);
+            }
+            Region::Lines { first, .. } => win.cursor_to(first, first_non_blank(buf, first)),
+            Region::Block { top, left, .. } => win.cursor_to(top, left),
+        }
+    }
+
+    /// `~`, switches the case of `count` chars and moves past them
+    pub(super) fn toggle_case(&mut self, count: usize) -> bool {
+        let (win, buf) = self.current();
+        let len = buf.line_len(win.cy);
+        if win.cx >= len {
+            return false;
+        }
+        let start = buf.pos_to_char(win.cy, win.cx);
+        let end = buf.pos_to_char(win.cy, win.cx + count);
+        self.change_case("g~", Region::Chars { start, end });
+        let (win, buf) = self.current();
+        win.cursor_to(win.cy, (win.cx + count).min(buf.line_len(win.cy)));
+        true
+    }
+
+    /// `r`, replaces `count` chars with `c`, a line break replaces them
+    /// all with a single one
+    pub(super) fn replace_chars(&mut self, c: char, count: usize) -> bool {
+        let (win, buf) = self.current();
+        if win.cx + count > buf.line_len(win.cy) {
+            return false;
+        }
+        let start = buf.pos_to_char(win.cy, win.cx);
+        buf.remove(start..start + count);
+        if c == '\r' || c == '\n' {
+            buf.insert(start, "\n");
+            win.cursor_to(win.cy + 1, 0);
+        } else {
+            buf.insert(start, &c.to_string().repeat(count));
+            win.cursor_to(win.cy, win.cx + count - 1);
+        }
+        true
+    }
+
+    /// `J` and `gJ`, joins `count` lines, at least two. `J` takes out the
+    /// indent of the joined lines and puts a space in between
+    pub(super) fn join(&mut self, count: usize, spaces: bool) -> bool {
+        let (win, buf) = self.current();
+        let last = buf.last_line();
+        if win.cy >= last {
+            return false;
+        }
+        let joins = (count.max(2) - 1).min(last - win.cy);
+        let mut col = 0;
+        for _ in 0..joins {
+            let line = win.cy;
+            let len = buf.line_len(line);
+            let at = buf.line_to_char(line) + len;
+            buf.join_lines(line);
+            col = len;
+            if !spaces {
+                continue;
+            }
+            let next = buf
+                .slice(at..buf.line_to_char(line) + buf.line_len(line))
+                .to_string();
+            let blanks = next.chars().take_while(|c| c.is_whitespace()).count();
+            buf.remove(at..at + blanks);
+            let ends_blank = len == 0 || buf.get_char(at - 1).is_some_and(|c| c.is_whitespace());
+            let rest = &next[next.len() - next.trim_start().len()..];
+            if !ends_blank && !rest.is_empty() && !rest.starts_with(')') {
+                buf.insert(at, " ");
+            }
+        }
+        win.cursor_to(win.cy, col);
+        true
+    }
+
+    /// `p` and `P`, puts the text in register `reg`, the last yanked or
+    /// deleted when None, after or before the cursor `count` times
+    pub(super) fn put(&mut self, before: bool, count: usize, reg: Option<char>) -> bool {
+        match self.register(reg) {
+            Some(register) => self.put_register(&register, before, count),
+            None => false,
+        }
+    }
+
+    /// puts text yanked or deleted into register `reg`. the system
+    /// clipboard gets it too for `"+` and `"*`, and when no register was
+    /// named and 'clipboard' says so
+    fn keep(&mut self, reg: Option<char>, register: Register, delete: bool) {
+        let clip = match reg {
+            Some(name @ ('+' | '*')) => Some(name),
+            None => self.options.clipboard_register(),
+            _ => None,
+        };
+        let text = register.text.clone();
+        if delete {
+            self.registers.delete(reg, register.clone());
+        } else {
+            self.registers.yank(reg, register.clone());
+        }
+        if let Some(name) = clip {
+            if reg.is_none() {
+                self.registers.yank(Some(name), register);
+            }
+            self.copy(text, name == '*');
+        }
+    }
+
+    /// puts `text` on the system clipboard, or the primary selection.
+    /// over ssh or when no program for it works, the terminal is asked to
+    /// do it
+    fn copy(&mut self, text: String, primary: bool) {
+        if clipboard::remote() || !clipboard::copy(&text, primary) {
+            self.copied = Some((text, primary));
+        }
+    }
+
+    /// what register `name` holds, the unnamed one or the clipboard as
+    /// 'clipboard' says when None. an error is shown when it's empty
+    pub(super) fn register(&mut self, name: Option<char>) -> Option<Register> {
+        let name = name.or(self.options.clipboard_register()).unwrap_or('"');
+        let register = match name {
+            '%' => Some(Register::new(self.current().1.name(), Kind::Charwise)),
+            '+' | '*' => self.paste(name),
+            _ => self.registers.get(name),
+        };
+        if register.is_none() {
+            self.message = format!("E353: Nothing in register {}", name);
+            self.message_is_error = true;
+        }
+        register
+    }
+
+    /// `"+` and `"*`, what is on the system clipboard. text copied from
+    /// here keeps its kind, other text is linewise when it ends in a line
+    /// break. what was last copied when the clipboard can't be read
+    fn paste(&mut self, name: char) -> Option<Register> {
+        let kept = self.registers.get(name);
+        let Some(text) = clipboard::paste(name == '*') else {
+            return kept;
+        };
+        match kept {
+            Some(register) if register.text == text => Some(register),
+            _ if text.is_empty() => None,
+            _ => {
+                let kind = if text.ends_with('\n') {
+                    Kind::Linewise
+                } else {
+                    Kind::Charwise
+                };
+                Some(Register::new(text, kind))
+            }
+        }
+    }
+
+    /// puts `register` after or before the cursor `count` times
+    pub(super) fn put_register(&mut self, register: &Register, before: bool, count: usize) -> bool {
+        let (win, buf) = self.current();
+        let lines_before = buf.line_count();
+        match register.kind {
+            Kind::Charwise => {
+                let len = buf.line_len(win.cy);
+                let col = if before || len == 0 {
+                    win.cx
+                } else {
+                    win.cx + 1
+                };
+                let at = buf.pos_to_char(win.cy, col);
+                let text = register.text.repeat(count);
+                buf.insert(at, &text);
+                let (line, col) = if text.contains('\n') {
+                    buf.char_to_pos(at)
+                } else {
+                    buf.char_to_pos(at + text.chars().count().saturating_sub(1))
+                };
+                win.cursor_to(line, col);
+            }
+            Kind::Linewise => {
+                let line = if before { win.cy } else { win.cy + 1 };
+                let text = register.text.repeat(count);
+                if line < buf.line_count() {
+                    buf.insert(buf.line_to_char(line), &text);
+                } else {
+                    let text = format!("\n{}", text.strip_suffix('\n').unwrap_or(&text));
+                    buf.insert(buf.len_chars(), &text);
+                }
+                win.cursor_to(line, first_non_blank(buf, line));
+            }
+            Kind::Blockwise => {
+                let len = buf.line_len(win.cy);
+                let col = if before || len == 0 {
+                    win.cx
+                } else {
+                    win.cx + 1
+                };
+                let lines = register.lines();
+                let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+                for (i, piece) in lines.iter().enumerate() {
+                    let line = win.cy + i;
+                    if line >= buf.line_count() {
+                        buf.insert(buf.len_chars(), "\n");
+                    }
+                    let len = buf.line_len(line);
+                    let pad = |s: &str| format!("{:width$}", s, width = width);
+                    let mut text = pad(piece).repeat(count - 1) + piece;
+                    if len > col {
+                        // the text after the block stays in its column
+                        text = format!("{:width$}", text, width = width * count);
+                    } else {
+                        text.insert_str(0, &" ".repeat(col - len));
+                    }
+                    buf.insert(buf.pos_to_char(line, col), &text);
+                }
+                win.cursor_to(win.cy, col);
+            }
+        }
+        let more = buf.line_count() - lines_before;
+        self.report(more, "more lines");
+        true
+    }
+}
+
+/// replaces the first `blanks` chars of `line` with `indent`
+fn set_indent(buf: &mut Buffer, line: usize, blanks: usize, indent: &str) {
+    let start = buf.line_to_char(line);
+    if buf.slice(start..start + blanks) != indent {
+        buf.remove(start..start + blanks);
+        buf.insert(start, indent);
+    }
+}
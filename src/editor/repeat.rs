@@ -0,0 +1,56 @@
+use super::{Editor, EditorMode};
+use crate::normal::Command;
+
+/// a change as it was made, for `.` to make again
+#[derive(Clone, Debug)]
+pub(super) struct Change {
+    cmd: Command,
+    /// what was typed in the insert the command started
+    typed: String,
+}
+
+impl Change {
+    pub(super) fn new(cmd: Command, typed: String) -> Self {
+        Self { cmd, typed }
+    }
+}
+
+impl Editor {
+    /// keeps `cmd` for `.` when it changed the text, which was at change
+    /// `changenr` before it, or started an insert, which is kept once it
+    /// ends
+    pub(super) fn keep_change(&mut self, cmd: Command, changenr: usize) {
+        if self.mode.is_insert() {
+            self.insertion.cmd = Some(cmd);
+        } else if self.current().1.changenr() != changenr {
+            self.last_change = Some(Change::new(cmd, String::new()));
+        }
+    }
+
+    /// `.`, makes the last change again, `count` instead of its own count
+    /// when given. false when there is none
+    pub(super) fn repeat_change(&mut self, count: Option<usize>) -> bool {
+        let Some(change) = self.last_change.clone() else {
+            return false;
+        };
+        let mut cmd = change.cmd;
+        if count.is_some() {
+            cmd.count = count;
+        }
+        self.normal_command(cmd);
+        match self.mode {
+            EditorMode::Insert => {
+                self.insertion.typed(&change.typed);
+                self.insert_text(&change.typed);
+            }
+            EditorMode::Replace | EditorMode::VirtualReplace => {
+                for c in change.typed.chars() {
+                    self.replace_char(c);
+                }
+            }
+            _ => return true,
+        }
+        self.finish_insert();
+        true
+    }
+}
@@ -0,0 +1,107 @@
+use super::{Editor, EditorMode};
+use crate::window::char_width;
+
+impl Editor {
+    /// `R` and `gR`, what gets typed overwrites the text `count` times
+    pub(super) fn start_replace(&mut self, virt: bool, count: usize) {
+        self.start_insert("i", count);
+        self.mode = if virt {
+            EditorMode::VirtualReplace
+        } else {
+            EditorMode::Replace
+        };
+    }
+
+    /// a char typed in replace mode
+    pub(super) fn replace_char(&mut self, c: char) {
+        self.insertion.typed(c.encode_utf8(&mut [0; 4]));
+        let virt = self.mode == EditorMode::VirtualReplace;
+        self.overwrite(c, virt);
+    }
+
+    /// puts `c` in place of the char under the cursor, or of the screen
+    /// columns it takes up when `virt`. a line break splits the line and
+    /// nothing is overwritten past the end of the line
+    pub(super) fn overwrite(&mut self, c: char, virt: bool) {
+        let (win, buf) = self.current();
+        let len = buf.line_len(win.cy);
+        let at = buf.pos_to_char(win.cy, win.cx);
+        if c == '\n' {
+            buf.split_line(win.cy, win.cx);
+            win.cursor_to(win.cy + 1, 0);
+            self.insertion.replaced(String::new());
+            return;
+        }
+        let mut end = at + usize::from(win.cx < len);
+        if virt && win.cx < len {
+            let ts = win.tabstop;
+            let vcol = win.vcol(buf, win.cy, win.cx);
+            let stop = vcol + char_width(c, vcol, ts);
+            // a tab is only taken out once what's typed fills it up
+            end = at;
+            let mut col = vcol;
+            for old in buf.line(win.cy).chars().skip(win.cx) {
+                let width = char_width(old, col, ts);
+                let fits = if old == '\t' {
+                    col + width <= stop
+                } else {
+                    col < stop
+                };
+                if !fits {
+                    break;
+                }
+                end += 1;
+                col += width;
+            }
+        }
+        let removed = buf.slice(at..end).to_string();
+        buf.remove(at..end);
+        buf.insert(at, c.encode_utf8(&mut [0; 4]));
+        win.cursor_to(win.cy, win.cx + 1);
+        self.insertion.replaced(removed);
+    }
+
+    /// backspace in replace mode, what the last char typed took the place
+    /// of comes back. before anything was typed it only moves the cursor
+    pub(super) fn replace_backspace(&mut self) {
+        let removed = self.insertion.unreplace();
+        let (win, buf) = self.current();
+        match removed {
+            Some(_) if win.cx == 0 => {
+                // a line break that was typed
+                let line = win.cy - 1;
+                let len = buf.line_len(line);
+                buf.join_lines(line);
+                win.cursor_to(line, len);
+            }
+            Some(text) => {
+                let at = buf.pos_to_char(win.cy, win.cx - 1);
+                buf.remove(at..at + 1);
+                buf.insert(at, &text);
+                win.cursor_to(win.cy, win.cx - 1);
+            }
+            None if win.cx > 0 => win.cursor_to(win.cy, win.cx - 1),
+            None => {}
+        }
+        self.insertion.backspace();
+    }
+
+    /// `gr{char}`, like `r` but overwriting screen columns, so a tab is
+    /// only replaced once `count` chars fill it up
+    pub(super) fn virtual_replace_chars(&mut self, c: char, count: usize) -> bool {
+        if c == '\r' || c == '\n' {
+            return self.replace_chars(c, count);
+        }
+        let (win, buf) = self.current();
+        if win.cx >= buf.line_len(win.cy) {
+            return false;
+        }
+        for _ in 0..count {
+            self.overwrite(c, true);
+        }
+        self.insertion.moved();
+        let win = &mut self.windows[self.current];
+        win.cursor_to(win.cy, win.cx - 1);
+        true
+    }
+}
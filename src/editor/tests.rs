@@ -0,0 +1,441 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+use rustix::io::Errno;
+
+use super::{Editor, EditorMode};
+use crate::buffer::Buffer;
+use crate::screen::{Screen, GUTTER, STATUS};
+use crate::terminal::Headless;
+
+/// an editor on `text` in a 10 by 40 headless terminal
+fn start(text: &str) -> (Editor, Headless) {
+    let term = Headless::new(10, 40);
+    let buffer = Buffer::new(text, PathBuf::from("test.txt"));
+    let editor = Editor::new(buffer, Box::new(term.clone()));
+    (editor, term)
+}
+
+/// handles everything fed to `term`, the editor stops when it runs out
+fn run(editor: &mut Editor) {
+    assert_eq!(editor.run(), Err(Errno::IO));
+}
+
+fn text(editor: &Editor) -> String {
+    let mut out = Vec::new();
+    editor.buffers[0].write_to(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// `text` after typing `keys` into an editor on it
+fn edited(text: &str, keys: &str) -> String {
+    let (mut editor, term) = start(text);
+    term.feed(keys.as_bytes());
+    run(&mut editor);
+    self::text(&editor)
+}
+
+#[test]
+fn keys_are_decoded_from_the_bytes_typed() {
+    let (mut editor, term) = start("one\ntwo\n");
+    // an arrow key arriving in pieces, then Esc on its own once the
+    // wait for more runs out
+    term.feed(b"A!");
+    term.feed(b"\x1b[");
+    term.feed(b"B?");
+    term.feed(b"\x1b");
+    term.pause();
+    term.feed(b"0x");
+    run(&mut editor);
+    assert_eq!(text(&editor), "one!\nwo?\n");
+}
+
+#[test]
+fn a_paste_is_one_edit() {
+    let (mut editor, term) = start("end\n");
+    term.feed(b"i\x1b[200~a\nb\x1b[20");
+    term.feed(b"1~\x1b");
+    term.pause();
+    run(&mut editor);
+    assert_eq!(text(&editor), "a\nbend\n");
+    term.feed(b"u");
+    run(&mut editor);
+    assert_eq!(text(&editor), "end\n");
+}
+
+#[test]
+fn wide_chars_take_two_columns() {
+    let (mut editor, term) = start("日本x\n");
+    term.feed(b"$");
+    run(&mut editor);
+    assert_eq!(term.row(0), " 1  日本x");
+    assert_eq!(term.cursor(), Some((0, 8)));
+    term.feed(b"h");
+    run(&mut editor);
+    assert_eq!(term.cursor(), Some((0, 6)));
+}
+
+#[test]
+fn the_line_after_the_final_newline_is_out_of_reach() {
+    let (mut editor, term) = start("one\ntwo\n");
+    term.feed(b"jjj");
+    run(&mut editor);
+    assert_eq!(editor.windows[0].cy, 1);
+    term.feed(b"gg\x06\x06");
+    run(&mut editor);
+    assert_eq!((editor.windows[0].cy, editor.windows[0].rowoff), (1, 1));
+    term.feed(b"gg\x04\x04");
+    run(&mut editor);
+    assert_eq!(editor.windows[0].cy, 1);
+    term.feed(b"gg\x1b[1;5F");
+    run(&mut editor);
+    assert_eq!(editor.windows[0].cy, 1);
+}
+
+#[test]
+fn clicks_move_the_cursor_and_the_wheel_scrolls() {
+    let text: String = (1..=30).map(|n| format!("line {}\n", n)).collect();
+    let (mut editor, term) = start(&text);
+    run(&mut editor);
+    assert!(term.mouse());
+    // SGR reports are 1 based, the text starts after 5 columns of line
+    // numbers
+    term.feed(b"\x1b[<0;8;3M\x1b[<0;8;3m");
+    run(&mut editor);
+    assert_eq!(term.cursor(), Some((2, 7)));
+    assert_eq!((editor.windows[0].cy, editor.windows[0].cx), (2, 2));
+    term.feed(b"\x1b[<65;8;3M");
+    run(&mut editor);
+    assert_eq!(term.row(0), "  4  line 4");
+}
+
+#[test]
+fn resizes_and_suspends_are_followed() {
+    let (mut editor, term) = start("text\n");
+    run(&mut editor);
+    assert_eq!(term.text().len(), 10);
+    term.resize(5, 20);
+    run(&mut editor);
+    assert_eq!(term.text().len(), 5);
+    assert_eq!(term.row(4), "---normal  test.txt");
+    term.feed(b"\x1a");
+    run(&mut editor);
+    assert_eq!(term.suspended(), 1);
+    assert!(term.is_raw());
+    term.feed(b":suspend\r");
+    run(&mut editor);
+    assert_eq!(term.suspended(), 2);
+}
+
+#[test]
+fn the_frame_shows_the_text_and_the_mode() {
+    let (mut editor, term) = start("one\n\ttwo\n");
+    term.feed(b"jA!");
+    run(&mut editor);
+    assert_eq!(
+        term.text(),
+        [
+            " 1  one",
+            " 2          two!",
+            " 3",
+            "~",
+            "~",
+            "~",
+            "~",
+            "~",
+            "~",
+            "---insert  test.txt [+]",
+        ]
+    );
+    assert_eq!(term.cursor(), Some((1, 16)));
+    let frame = term.frame().unwrap();
+    assert_eq!(frame.cell(9, 0).style, GUTTER);
+    assert_eq!(frame.cell(9, 3).style, STATUS);
+}
+
+#[test]
+fn turning_undofile_on_reads_the_history() {
+    let dir = std::env::temp_dir().join(format!("ri-undofile-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file.txt");
+    fs::write(&path, "one\n").unwrap();
+    let term = Headless::new(10, 40);
+    let mut editor = Editor::new(Buffer::open(&path).unwrap(), Box::new(term.clone()));
+    let set = format!(":set undodir={} undofile\r", dir.display());
+    term.feed(set.as_bytes());
+    term.feed(b"x:w\r");
+    run(&mut editor);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "ne\n");
+    let mut editor = Editor::new(Buffer::open(&path).unwrap(), Box::new(term.clone()));
+    term.feed(b"u");
+    run(&mut editor);
+    assert_eq!(text(&editor), "ne\n");
+    term.feed(set.as_bytes());
+    term.feed(b"u");
+    run(&mut editor);
+    assert_eq!(text(&editor), "one\n");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn the_clipboard_is_reached_through_helpers_or_the_terminal() {
+    // PATH and SSH_TTY are shared by the tests running alongside, so the
+    // test runs in a process of its own that finds the helpers in PATH
+    let dir = env::temp_dir().join(format!("ri-clipboard-{}", std::process::id()));
+    let clip = dir.join("clip");
+    fs::create_dir_all(&dir).unwrap();
+    // helpers that keep the clipboard in a file
+    for (name, cat) in [("wl-copy", "cat >"), ("wl-paste", "cat")] {
+        let helper = dir.join(name);
+        let script = format!("#!/bin/sh\n{} {}\n", cat, clip.display());
+        fs::write(&helper, script).unwrap();
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let mut paths = vec![dir.clone()];
+    paths.extend(env::split_paths(&env::var_os("PATH").unwrap_or_default()));
+    let test = "editor::tests::clipboard_with_helpers_in_path";
+    let out = Command::new(env::current_exe().unwrap())
+        .args(["--exact", test, "--ignored", "--nocapture"])
+        .env("PATH", env::join_paths(paths).unwrap())
+        .env("RI_TEST_CLIPBOARD", &clip)
+        .env_remove("SSH_TTY")
+        .env_remove("SSH_CONNECTION")
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        out.status.success() && stdout.contains("1 passed"),
+        "{}{}",
+        stdout,
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+/// the part of the test above run in a child process
+#[test]
+#[ignore]
+fn clipboard_with_helpers_in_path() {
+    let clip = PathBuf::from(env::var_os("RI_TEST_CLIPBOARD").unwrap());
+    let (mut editor, term) = start("one\ntwo\n");
+    term.feed(b":set clipboard=unnamedplus\ryy");
+    run(&mut editor);
+    assert_eq!(fs::read_to_string(&clip).unwrap(), "one\n");
+    assert_eq!(term.frame().unwrap().clipboard, None);
+    fs::write(&clip, "pasted\n").unwrap();
+    term.feed(b"p");
+    run(&mut editor);
+    assert_eq!(text(&editor), "one\npasted\ntwo\n");
+
+    // over ssh the terminal is asked to do it
+    env::set_var("SSH_TTY", "/dev/pts/0");
+    term.feed(b"jyw");
+    run(&mut editor);
+    let frame = term.frame().unwrap();
+    assert_eq!(frame.clipboard, Some(("two".to_string(), false)));
+    let out = Screen::default().render(frame);
+    assert!(out.contains("\x1b]52;c;dHdv\x07"));
+}
+
+#[test]
+fn macros_keep_other_windows_in_their_buffer() {
+    let (mut editor, term) = start(":sp\rggdG:wincmd w\rx\nb\nc\nd\n");
+    term.feed(b"\"qy$G@q");
+    run(&mut editor);
+    assert_eq!(text(&editor), "");
+    assert_eq!(editor.windows.len(), 2);
+    assert_eq!(term.row(0), " 1");
+}
+
+#[test]
+fn ctrl_c_stops_a_macro_that_never_fails() {
+    let (mut editor, term) = start("0@a\n");
+    term.feed(b"\"ay$@a");
+    term.feed(b"\x03");
+    run(&mut editor);
+    assert!(editor.replay.is_empty());
+    assert_eq!(term.row(9), "---normal  test.txt  Interrupted");
+}
+
+#[test]
+fn finds_work_with_operators_even_when_a_till_does_not_move() {
+    assert_eq!(edited("ab)c\n", "ldt)"), "a)c\n");
+    assert_eq!(edited("ab)c\n", "lct)X\x1b"), "aX)c\n");
+    assert_eq!(edited("a(bc\n", "$dT("), "a(c\n");
+    // backwards the char under the cursor isn't part of it
+    assert_eq!(edited("a(bc\n", "2ldT("), "a(bc\n");
+    assert_eq!(edited("a,b,c,d\n", "d2f,"), "c,d\n");
+    assert_eq!(edited("a,b,c,d\n", "$dF,"), "a,b,cd\n");
+    // no char to find, nothing happens
+    assert_eq!(edited("a,b\n", "dfx"), "a,b\n");
+}
+
+#[test]
+fn semicolon_and_comma_repeat_the_last_find() {
+    assert_eq!(edited("a,b,c,d\n", "f,;x"), "a,bc,d\n");
+    assert_eq!(edited("a,b,c,d\n", "f,;;,x"), "a,bc,d\n");
+    // a till doesn't get stuck in front of the char
+    assert_eq!(edited("a,b,c,d\n", "t,;x"), "a,,c,d\n");
+    assert_eq!(edited("a,b,c,d\n", "$T,;x"), "a,b,,d\n");
+    assert_eq!(edited("a,b,c,d\n", "f,d;"), "ac,d\n");
+}
+
+#[test]
+fn linewise_operators_stop_at_the_last_line() {
+    assert_eq!(edited("one\ntwo\n", "Gdd"), "one\n");
+    assert_eq!(edited("one\ntwo\n", "jddp"), "one\ntwo\n");
+    assert_eq!(edited("one\ntwo\n", "yGGp"), "one\ntwo\none\ntwo\n");
+    assert_eq!(edited("one\ntwo\n", "3dd"), "");
+    assert_eq!(edited("one\ntwo\n", "3yyjp"), "one\ntwo\none\ntwo\n");
+    assert_eq!(edited("one\ntwo\n", "jJ"), "one\ntwo\n");
+    assert_eq!(edited("one\ntwo\n", "yyjVp"), "one\none\n");
+    assert_eq!(edited("one\ntwo", "Gddp"), "one\ntwo");
+    let (mut editor, term) = start("one\ntwo\nthree\n");
+    term.feed(b"=G");
+    run(&mut editor);
+    assert_eq!(editor.message, "3 lines indented ");
+    term.feed(b"Gdd");
+    run(&mut editor);
+    assert_eq!(text(&editor), "one\ntwo\n");
+    assert_eq!(editor.windows[0].cy, 1);
+}
+
+#[test]
+fn operators_take_text_objects() {
+    assert_eq!(edited("f(a, b)\n", "fbci(x\x1b"), "f(x)\n");
+    // no bracket around the cursor, the `x` after it deletes a char
+    assert_eq!(edited("f(a, b)\n", "ci(x\x1b"), "(a, b)\n");
+    assert_eq!(edited("x = \"a b\";\n", "fbda\""), "x =;\n");
+    assert_eq!(edited("a\nb\n\nc\n", "dap"), "c\n");
+    assert_eq!(edited("one two three\n", "wdaw"), "one three\n");
+    assert_eq!(edited("<p>one</p>\n", "fncitx\x1b"), "<p>x</p>\n");
+}
+
+/// an editor on "abc\n" after `x`, `x`, `u` and `$x`: state 1 is "bc",
+/// state 2 "c" on the branch undone and state 3 "b" on the new one
+fn branched() -> (Editor, Headless) {
+    let (mut editor, term) = start("abc\n");
+    term.feed(b"xxu$x");
+    run(&mut editor);
+    assert_eq!(text(&editor), "b\n");
+    (editor, term)
+}
+
+/// `text` of `editor` after typing `keys`
+fn after(editor: &mut Editor, term: &Headless, keys: &str) -> String {
+    term.feed(keys.as_bytes());
+    run(editor);
+    text(editor)
+}
+
+#[test]
+fn undo_and_redo_follow_the_newest_branch() {
+    let (mut editor, term) = branched();
+    assert_eq!(after(&mut editor, &term, "u"), "bc\n");
+    assert_eq!(after(&mut editor, &term, "\x12"), "b\n");
+    assert_eq!(after(&mut editor, &term, "uu"), "abc\n");
+    assert_eq!(after(&mut editor, &term, "2\x12"), "b\n");
+    assert_eq!(after(&mut editor, &term, "\x12"), "b\n");
+    assert_eq!(editor.message, "Already at newest change");
+}
+
+#[test]
+fn g_minus_and_g_plus_go_across_branches() {
+    let (mut editor, term) = branched();
+    assert_eq!(after(&mut editor, &term, "g-"), "c\n");
+    assert_eq!(after(&mut editor, &term, "g-"), "bc\n");
+    assert_eq!(after(&mut editor, &term, "g-"), "abc\n");
+    assert_eq!(after(&mut editor, &term, "g-"), "abc\n");
+    assert_eq!(editor.message, "Already at oldest change");
+    assert_eq!(after(&mut editor, &term, "2g+"), "c\n");
+    // redo now goes down the branch visited last
+    assert_eq!(after(&mut editor, &term, "u\x12"), "c\n");
+    assert_eq!(after(&mut editor, &term, "g+"), "b\n");
+}
+
+#[test]
+fn earlier_and_later_go_by_count_or_time() {
+    let (mut editor, term) = branched();
+    assert_eq!(after(&mut editor, &term, ":earlier 2\r"), "bc\n");
+    assert_eq!(after(&mut editor, &term, ":later\r"), "c\n");
+    assert_eq!(after(&mut editor, &term, ":earlier 1h\r"), "abc\n");
+    assert_eq!(after(&mut editor, &term, ":later 10s\r"), "b\n");
+    assert_eq!(after(&mut editor, &term, ":earlier 1x\r"), "b\n");
+    assert!(editor.message.starts_with("E475"), "{}", editor.message);
+}
+
+#[test]
+fn big_u_puts_back_the_line_and_undoes_itself() {
+    let (mut editor, term) = start("abc def\nghi\n");
+    assert_eq!(after(&mut editor, &term, "xwx"), "bc ef\nghi\n");
+    assert_eq!(after(&mut editor, &term, "U"), "abc def\nghi\n");
+    assert_eq!(after(&mut editor, &term, "U"), "bc ef\nghi\n");
+    // `U` is a change of its own that `u` takes back
+    assert_eq!(after(&mut editor, &term, "U"), "abc def\nghi\n");
+    assert_eq!(after(&mut editor, &term, "u"), "bc ef\nghi\n");
+    // it works on the line changed last, wherever the cursor is
+    assert_eq!(after(&mut editor, &term, "j0x"), "bc ef\nhi\n");
+    assert_eq!(after(&mut editor, &term, "kU"), "bc ef\nghi\n");
+}
+
+#[test]
+fn undo_puts_the_cursor_back_where_the_change_was() {
+    let (mut editor, term) = start("one\n    two\nthree\n");
+    term.feed(b"jfwx2jdd");
+    run(&mut editor);
+    assert_eq!(text(&editor), "one\n    to\n");
+    let cursor = |editor: &Editor| (editor.windows[0].cy, editor.windows[0].cx);
+    term.feed(b"gg");
+    run(&mut editor);
+    // the cursor was in the line, so it goes back to where it was
+    assert_eq!(after(&mut editor, &term, "u"), "one\n    to\nthree\n");
+    assert_eq!(cursor(&editor), (2, 4));
+    assert_eq!(after(&mut editor, &term, "u"), "one\n    two\nthree\n");
+    assert_eq!(cursor(&editor), (1, 5));
+    // a redo goes to the start of what it changes
+    assert_eq!(after(&mut editor, &term, "gg\x12"), "one\n    to\nthree\n");
+    assert_eq!(cursor(&editor), (1, 5));
+}
+
+#[test]
+fn dot_repeats_the_last_change() {
+    // a new count takes the place of the old one
+    assert_eq!(edited("abcdefgh\n", "2x3."), "fgh\n");
+    assert_eq!(edited("abcdefgh\n", "2x."), "efgh\n");
+    assert_eq!(edited("a\nb\nc\nd\ne\n", "dd2."), "d\ne\n");
+    assert_eq!(edited("one two three\n", "cwuno\x1bw."), "uno uno three\n");
+    assert_eq!(edited("a\nb\nc\n", "A;\x1bj.j."), "a;\nb;\nc;\n");
+    assert_eq!(edited("x\ny\n", "3ia\x1bj."), "aaax\naaay\n");
+    assert_eq!(edited("x\ny\n", "3ia\x1bj2."), "aaax\naay\n");
+    assert_eq!(edited("abcd\nefgh\n", "Rxy\x1bj0."), "xycd\nxygh\n");
+    assert_eq!(edited("one\n", "oa\x1b.."), "one\na\na\na\n");
+    assert_eq!(edited("f(a) g(b)\n", "faci(x\x1bfb."), "f(x) g(x)\n");
+    // undo isn't a change to repeat
+    assert_eq!(edited("abc\n", "xu."), "bc\n");
+}
+
+#[test]
+fn registers_are_named_with_a_quote() {
+    assert_eq!(edited("a\nb\n", "yyj\"_ddp"), "a\na\n");
+    assert_eq!(edited("a b\n", "\"qyiww\"Qyiw\"qP"), "a abb\n");
+    assert_eq!(edited("x\n", "ihi\x1b\".p"), "hihix\n");
+    assert_eq!(edited("x\n", "\"%p"), "xtest.txt\n");
+}
+
+#[test]
+fn an_esc_read_together_with_the_next_key_is_recorded() {
+    let (mut editor, term) = start("x\n");
+    // the Esc and the `q` after it arrive as alt+q
+    term.feed(b"qqahi");
+    term.feed(b"\x1bq");
+    run(&mut editor);
+    assert_eq!(text(&editor), "xhi\n");
+    assert_eq!(editor.mode, EditorMode::Normal);
+    term.feed(b"@q");
+    run(&mut editor);
+    assert_eq!(text(&editor), "xhihi\n");
+    assert_eq!(editor.mode, EditorMode::Normal);
+}
@@ -0,0 +1,143 @@
+use std::time::SystemTime;
+
+use super::Editor;
+
+/// how long ago `time` was, the way undo messages put it
+fn ago(time: SystemTime) -> String {
+    let secs = SystemTime::now()
+        .duration_since(time)
+        .map_or(0, |d| d.as_secs());
+    let (n, unit) = match secs {
+        0..=99 => (secs, "second"),
+        100..=5999 => (secs / 60, "minute"),
+        _ => (secs / 3600, "hour"),
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
+/// seconds in a `:earlier` or `:later` argument like `10m`, or the
+/// number of changes when it has no unit
+fn travel_arg(arg: &str) -> Result<(u64, bool), String> {
+    if arg.is_empty() {
+        return Ok((1, false));
+    }
+    let digits = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let (n, unit) = arg.split_at(digits);
+    let n: u64 = n
+        .parse()
+        .map_err(|_| format!("E475: Invalid argument: {}", arg))?;
+    let scale = match unit {
+        "" => return Ok((n, false)),
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("E475: Invalid argument: {}", arg)),
+    };
+    Ok((n.saturating_mul(scale), true))
+}
+
+impl Editor {
+    /// `u`, undoes the last `count` changes
+    pub(super) fn undo(&mut self, count: usize) {
+        let tree = self.current().1.undo_tree();
+        let mut to = tree.seq();
+        for _ in 0..count {
+            if to == 0 {
+                break;
+            }
+            to = tree.change(to).parent;
+        }
+        self.undo_to(to, false);
+    }
+
+    /// Ctrl-R, redoes `count` changes that were undone
+    pub(super) fn redo(&mut self, count: usize) {
+        let tree = self.current().1.undo_tree();
+        let mut to = tree.seq();
+        for _ in 0..count {
+            match tree.change(to).redo {
+                Some(child) => to = child,
+                None => break,
+            }
+        }
+        self.undo_to(to, true);
+    }
+
+    /// `g-` and `g+`, goes `count` states back or forward in the order
+    /// they were made, across branches of the undo tree
+    pub(super) fn undo_steps(&mut self, count: usize, later: bool) {
+        let tree = self.current().1.undo_tree();
+        let to = if later {
+            tree.seq().saturating_add(count).min(tree.last())
+        } else {
+            tree.seq().saturating_sub(count)
+        };
+        self.undo_to(to, later);
+    }
+
+    /// `:earlier` and `:later`, by a number of changes or a time
+    pub(super) fn time_travel(&mut self, arg: &str, later: bool) -> Result<(), String> {
+        let (n, by_time) = travel_arg(arg)?;
+        if !by_time {
+            self.undo_steps(n as usize, later);
+            return Ok(());
+        }
+        let to = self.current().1.undo_tree().by_time(n, later);
+        self.undo_to(to, later);
+        Ok(())
+    }
+
+    /// `U`, puts the line the last changes were made in back the way it
+    /// was before them. it's a change of its own, so `U` again redoes
+    pub(super) fn undo_line(&mut self) -> bool {
+        let (win, buf) = self.current();
+        match buf.undo_line() {
+            Some(line) => {
+                win.cursor_to(line, win.cx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// brings the current buffer to undo state `seq` and tells what was
+    /// done. `later` says which end of the history was hit when there is
+    /// nowhere to go
+    fn undo_to(&mut self, seq: usize, later: bool) {
+        let (win, buf) = self.current();
+        let steps = buf.undo_to(seq);
+        let Some(&(last, redo)) = steps.last() else {
+            let end = if later { "newest" } else { "oldest" };
+            self.message = format!("Already at {} change", end);
+            return;
+        };
+        let tree = buf.undo_tree();
+        let change = tree.change(last);
+        // the cursor goes to the first line changed. an undo puts it back
+        // where it was when it was in that line, a redo on the change
+        let at = change.edits.iter().map(|edit| edit.at).min().unwrap_or(0);
+        let (line, col) = buf.char_to_pos(at.min(buf.len_chars()));
+        let line = line.min(buf.last_line());
+        let col = match change.cursor {
+            _ if redo => col,
+            (cy, cx) if cy == line => cx,
+            _ => buf
+                .line(line)
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .count(),
+        };
+        win.cursor_to(line, col.min(buf.line_len(line)));
+        let n = steps.len();
+        let how = if redo { "after" } else { "before" };
+        self.message = format!(
+            "{} change{}; {} #{}  {}",
+            n,
+            if n == 1 { "" } else { "s" },
+            how,
+            last,
+            ago(tree.change(tree.seq()).time)
+        );
+    }
+}
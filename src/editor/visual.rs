@@ -0,0 +1,323 @@
+use super::operator::Region;
+use super::{Editor, EditorMode};
+use crate::normal::{Command, Step};
+use crate::register::{Kind, Register};
+
+/// a visual selection as it was left, for `gv`
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Visual {
+    pub(super) mode: EditorMode,
+    pub(super) anchor: (usize, usize),
+    pub(super) cursor: (usize, usize),
+    /// a block went to the end of every line
+    pub(super) to_eol: bool,
+}
+
+/// what a key does to the selection besides its operator
+#[derive(Clone, Copy, PartialEq)]
+enum Extend {
+    Keep,
+    /// the whole lines of the selection
+    Lines,
+    /// a block to the end of its lines, whole lines otherwise
+    Eol,
+}
+
+/// the operator a key runs on the selection
+fn visual_operator(keys: &str) -> Option<(&str, Extend)> {
+    Some(match keys {
+        "d" | "x" => ("d", Extend::Keep),
+        "X" => ("d", Extend::Lines),
+        "D" => ("d", Extend::Eol),
+        "y" => ("y", Extend::Keep),
+        "Y" => ("y", Extend::Lines),
+        "c" | "s" => ("c", Extend::Keep),
+        "C" => ("c", Extend::Eol),
+        "S" | "R" => ("c", Extend::Lines),
+        "~" => ("g~", Extend::Keep),
+        "u" => ("gu", Extend::Keep),
+        "U" => ("gU", Extend::Keep),
+        "<" | ">" | "=" | "g~" | "gu" | "gU" | "g?" => (keys, Extend::Keep),
+        _ => return None,
+    })
+}
+
+impl Editor {
+    /// `v`, `V` and Ctrl-V, the selection starts at the cursor
+    pub(super) fn start_visual(&mut self, mode: EditorMode) {
+        let win = &mut self.windows[self.current];
+        win.anchor = Some((win.cy, win.cx));
+        self.mode = mode;
+    }
+
+    /// `gv`, selects what was selected last, false when nothing was
+    pub(super) fn reselect(&mut self) -> bool {
+        let Some(last) = self.last_visual.take() else {
+            return false;
+        };
+        // the current selection is what the next `gv` gets back
+        self.leave_visual();
+        let (win, buf) = self.current();
+        let clamp = |(line, col): (usize, usize)| {
+            let line = line.min(buf.last_line());
+            (line, col.min(buf.line_len(line)))
+        };
+        let cursor = clamp(last.cursor);
+        win.anchor = Some(clamp(last.anchor));
+        win.cursor_to(cursor.0, cursor.1);
+        if last.to_eol {
+            win.end(buf);
+        }
+        self.mode = last.mode;
+        true
+    }
+
+    /// a key in visual mode, returns true when the editor should exit
+    pub(super) fn visual_key(&mut self, c: char) -> bool {
+        if c == '\x1b' {
+            self.pending.clear();
+            self.leave_visual();
+            return false;
+        }
+        if c == 'q' && self.recording.is_some() && self.pending.is_empty() {
+            self.stop_recording();
+            return false;
+        }
+        let quit = match self.pending.push_visual(c) {
+            Step::Pending => false,
+            Step::Invalid => {
+                self.stop_replay();
+                false
+            }
+            Step::Done(cmd) => self.visual_command(cmd),
+        };
+        self.clamp_cursor();
+        quit
+    }
+
+    fn visual_command(&mut self, cmd: Command) -> bool {
+        if let Some(motion) = self.motion(&cmd.keys) {
+            if !self.move_cursor(motion, cmd.count) {
+                self.stop_replay();
+            }
+            return false;
+        }
+        if self.view_command(&cmd) || self.select_object(&cmd.keys, cmd.count1()) {
+            return false;
+        }
+        let count = cmd.count1();
+        let keys = cmd.keys.as_str();
+        let mode = match keys {
+            "v" => Some(EditorMode::Visual),
+            "V" => Some(EditorMode::VisualLine),
+            "\x16" => Some(EditorMode::VisualBlock),
+            _ => None,
+        };
+        if let Some(mode) = mode {
+            if mode == self.mode {
+                self.leave_visual();
+            } else {
+                self.mode = mode;
+            }
+            return false;
+        }
+        let block = self.mode == EditorMode::VisualBlock;
+        let (win, _) = self.current();
+        let Some(anchor) = win.anchor else {
+            return false;
+        };
+        match keys {
+            "o" => {
+                win.anchor = Some((win.cy, win.cx));
+                win.cursor_to(anchor.0, anchor.1);
+            }
+            "O" if block => {
+                win.anchor = Some((anchor.0, win.cx));
+                win.cursor_to(win.cy, anchor.1);
+            }
+            "O" => {
+                win.anchor = Some((win.cy, win.cx));
+                win.cursor_to(anchor.0, anchor.1);
+            }
+            "gv" => _ = self.reselect(),
+            ":" => {
+                self.leave_visual();
+                self.mode = EditorMode::Command;
+            }
+            "I" | "A" if block => self.block_insert(keys == "A"),
+            "I" | "A" => {
+                let Some(region) = self.visual_region(Extend::Keep) else {
+                    return false;
+                };
+                self.leave_visual();
+                let (win, buf) = self.current();
+                let (first, last) = region.lines(buf);
+                win.cy = if keys == "I" { first } else { last };
+                self.start_insert(keys, 1);
+            }
+            "p" | "P" => {
+                let Some(region) = self.visual_region(Extend::Keep) else {
+                    return false;
+                };
+                self.leave_visual();
+                self.replace_selection(region, keys == "P", count, cmd.register);
+            }
+            "J" | "gJ" => {
+                let Some(region) = self.visual_region(Extend::Lines) else {
+                    return false;
+                };
+                self.leave_visual();
+                if let Region::Lines { first, last } = region {
+                    self.current().0.cy = first;
+                    self.join(last - first + 1, keys == "J");
+                }
+            }
+            keys if keys.starts_with('r') => {
+                let (Some(c), Some(region)) =
+                    (keys.chars().nth(1), self.visual_region(Extend::Keep))
+                else {
+                    return false;
+                };
+                self.leave_visual();
+                if c != '\r' {
+                    self.map_chars(region, |old| if old == '\n' { old } else { c });
+                }
+            }
+            keys => {
+                let Some((op, extend)) = visual_operator(keys) else {
+                    return false;
+                };
+                let Some(region) = self.visual_region(extend) else {
+                    return false;
+                };
+                self.leave_visual();
+                match op {
+                    "<" | ">" => self.shift(region, op == ">", count),
+                    _ => _ = self.apply_operator(op, region, cmd.register),
+                }
+            }
+        }
+        false
+    }
+
+    /// the region of the selection, `extend` as an operator key asks
+    fn visual_region(&mut self, extend: Extend) -> Option<Region> {
+        let sel = self.selection()?;
+        let (_, buf) = self.current();
+        Some(match (sel.kind, extend) {
+            (Kind::Blockwise, Extend::Eol) => Region::Block {
+                top: sel.start.0,
+                bottom: sel.end.0,
+                left: sel.left,
+                right: None,
+            },
+            (Kind::Linewise, _) | (_, Extend::Lines | Extend::Eol) => Region::Lines {
+                first: sel.start.0,
+                last: sel.end.0,
+            },
+            (Kind::Charwise, Extend::Keep) => {
+                // the line break is selected when the cursor is past the text
+                let start = buf.pos_to_char(sel.start.0, sel.start.1);
+                let end = buf.pos_to_char(sel.end.0, sel.end.1) + 1;
+                Region::Chars {
+                    start,
+                    end: end.min(buf.len_chars()),
+                }
+            }
+            (Kind::Blockwise, Extend::Keep) => Region::Block {
+                top: sel.start.0,
+                bottom: sel.end.0,
+                left: sel.left,
+                right: sel.right.map(|right| right + 1),
+            },
+        })
+    }
+
+    /// a text object typed in visual mode selects it, an object of whole
+    /// lines switches to selecting lines
+    fn select_object(&mut self, keys: &str, count: usize) -> bool {
+        if !(keys.starts_with(['i', 'a']) && keys.chars().count() == 2) {
+            return false;
+        }
+        let Some(region) = self.text_object(keys, count) else {
+            return false;
+        };
+        let (win, buf) = self.current();
+        match region {
+            Region::Lines { first, last } => {
+                win.anchor = Some((first, 0));
+                win.cursor_to(last, 0);
+                self.mode = EditorMode::VisualLine;
+            }
+            Region::Chars { start, end } => {
+                win.anchor = Some(buf.char_to_pos(start));
+                let (line, col) = buf.char_to_pos(end.max(start + 1) - 1);
+                win.cursor_to(line, col);
+                if self.mode != EditorMode::VisualBlock {
+                    self.mode = EditorMode::Visual;
+                }
+            }
+            Region::Block { .. } => {}
+        }
+        true
+    }
+
+    /// `I` and `A` on a block, what gets typed goes in front of or after
+    /// the block on every line
+    fn block_insert(&mut self, append: bool) {
+        let Some(sel) = self.selection() else {
+            return;
+        };
+        self.leave_visual();
+        let col = if append {
+            sel.right.map(|right| right + 1)
+        } else {
+            Some(sel.left)
+        };
+        let (win, buf) = self.current();
+        let top = sel.start.0;
+        let len = buf.line_len(top);
+        let at = match col {
+            Some(col) if append && len < col => {
+                buf.insert(buf.pos_to_char(top, len), &" ".repeat(col - len));
+                col
+            }
+            Some(col) => col.min(len),
+            None => len,
+        };
+        win.cursor_to(top, at);
+        self.start_insert("i", 1);
+        self.insertion.block(top + 1..=sel.end.0, col, append);
+    }
+
+    /// `p` and `P` in visual mode, the selection is replaced with what is
+    /// in register `reg`. `p` leaves what it replaced to be put next, `P`
+    /// doesn't
+    fn replace_selection(&mut self, region: Region, keep: bool, count: usize, reg: Option<char>) {
+        let Some(register) = self.register(reg) else {
+            return;
+        };
+        self.delete(region, Some('_').filter(|_| keep));
+        let (win, buf) = self.current();
+        let before = match region {
+            Region::Lines { first, .. } => first <= buf.last_line(),
+            _ => true,
+        };
+        let put = match (region, register.kind) {
+            (Region::Lines { .. }, Kind::Charwise) => {
+                Register::new(format!("{}\n", register.text), Kind::Linewise)
+            }
+            (Region::Lines { .. }, _) | (_, Kind::Charwise | Kind::Blockwise) => register.clone(),
+            (_, Kind::Linewise) => {
+                // lines put into the middle of a line go on their own
+                buf.split_line(win.cy, win.cx);
+                win.cursor_to(win.cy + 1, 0);
+                register.clone()
+            }
+        };
+        if !before {
+            win.cy = win.cy.min(buf.last_line());
+        }
+        self.put_register(&put, before, count);
+    }
+}
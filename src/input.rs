@@ -0,0 +1,511 @@
+use std::collections::VecDeque;
+
+/// shift, alt and ctrl as xterm encodes them in the second parameter of
+/// a CSI sequence, which is one more than these bits
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const SHIFT: Modifiers = Modifiers(1);
+    pub const ALT: Modifiers = Modifiers(2);
+    pub const CTRL: Modifiers = Modifiers(4);
+
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn from_param(param: u32) -> Self {
+        // meta is reported separately but means alt everywhere that matters
+        let bits = param.saturating_sub(1) as u8;
+        Modifiers((bits & 7) | if bits & 8 != 0 { 2 } else { 0 })
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum EditorKey {
+    ArrowLeft,
+    ArrowRight,
+    ArrowUp,
+    ArrowDown,
+    DelKey,
+    HomeKey,
+    EndKey,
+    PageUp,
+    PageDown,
+    Backspace,
+    Insert,
+    /// F1 to F12
+    F(u8),
+    /// a single byte, printable ascii or a control character
+    K(u8),
+    /// a non-ascii character
+    Char(char),
+    /// a key pressed together with shift, alt or ctrl, for the keys where
+    /// the terminal can tell
+    Mod(Modifiers, Box<EditorKey>),
+    /// text pasted into the terminal while bracketed paste is on
+    Paste(String),
+    Mouse(MouseEvent),
+}
+
+impl EditorKey {
+    /// alt+key without the alt, None for keys typed without it
+    pub fn without_alt(&self) -> Option<EditorKey> {
+        match self {
+            EditorKey::Mod(mods, key) if mods.contains(Modifiers::ALT) => {
+                let mods = Modifiers(mods.0 & !Modifiers::ALT.0);
+                Some(with_mods(mods, (**key).clone()))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MouseKind {
+    Press(MouseButton),
+    /// the mouse moved with a button held down
+    Drag(MouseButton),
+    Release,
+    ScrollUp,
+    ScrollDown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MouseEvent {
+    pub kind: MouseKind,
+    /// screen row, 0 based
+    pub row: usize,
+    /// screen column, 0 based
+    pub col: usize,
+    pub mods: Modifiers,
+}
+
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// outcome of trying to decode the front of the queue
+enum Decoded {
+    Key(EditorKey, usize),
+    /// a complete sequence the editor has no use for
+    Ignore(usize),
+    /// the bytes so far could be the start of a longer sequence
+    Incomplete,
+}
+
+/// turns the bytes read from the terminal into keys
+///
+/// bytes are queued as they arrive and decoded once a whole sequence is
+/// there, so reads may split sequences anywhere. an ESC that isn't
+/// followed by anything within the timeout is the Esc key itself
+#[derive(Debug, Default)]
+pub struct InputDecoder {
+    queue: VecDeque<u8>,
+    /// how much of a paste has been searched for its end already, so a
+    /// large paste arriving in pieces isn't searched over and over
+    paste_scanned: usize,
+}
+
+impl InputDecoder {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.queue.extend(bytes);
+    }
+
+    /// true when part of a sequence is waiting for the rest of it
+    pub fn is_pending(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// decodes the next key. with `timed_out` set no more bytes are
+    /// coming, so a partial sequence is taken apart instead of waited on
+    pub fn next_key(&mut self, timed_out: bool) -> Option<EditorKey> {
+        loop {
+            if self.queue.is_empty() {
+                return None;
+            }
+            if self.queue.iter().take(PASTE_START.len()).eq(PASTE_START) {
+                // a paste is never cut short, however slowly it arrives
+                return self.next_paste();
+            }
+            let buf = self.queue.make_contiguous();
+            match decode(buf) {
+                Decoded::Key(key, len) => {
+                    self.queue.drain(..len);
+                    return Some(key);
+                }
+                Decoded::Ignore(len) => {
+                    self.queue.drain(..len);
+                }
+                Decoded::Incomplete if timed_out => {
+                    let (key, len) = decode_partial(buf);
+                    self.queue.drain(..len);
+                    return Some(key);
+                }
+                Decoded::Incomplete => return None,
+            }
+        }
+    }
+}
+
+impl InputDecoder {
+    fn next_paste(&mut self) -> Option<EditorKey> {
+        let buf = self.queue.make_contiguous();
+        let from = self
+            .paste_scanned
+            .saturating_sub(PASTE_END.len() - 1)
+            .max(PASTE_START.len());
+        let Some(end) = buf[from..]
+            .windows(PASTE_END.len())
+            .position(|w| w == PASTE_END)
+        else {
+            self.paste_scanned = buf.len();
+            return None;
+        };
+        let end = from + end;
+        let text = String::from_utf8_lossy(&buf[PASTE_START.len()..end]).into_owned();
+        self.queue.drain(..end + PASTE_END.len());
+        self.paste_scanned = 0;
+        Some(EditorKey::Paste(text))
+    }
+}
+
+fn with_mods(mods: Modifiers, key: EditorKey) -> EditorKey {
+    if mods == Modifiers::default() {
+        key
+    } else {
+        EditorKey::Mod(mods, Box::new(key))
+    }
+}
+
+/// what the start of an unfinished sequence means on its own
+fn decode_partial(buf: &[u8]) -> (EditorKey, usize) {
+    match buf {
+        // alt+[ and alt+O look like the start of a sequence
+        [b'\x1b', c @ (b'[' | b'O')] => (with_mods(Modifiers::ALT, EditorKey::K(*c)), 2),
+        _ => (EditorKey::K(buf[0]), 1),
+    }
+}
+
+fn decode(buf: &[u8]) -> Decoded {
+    match buf[0] {
+        b'\x1b' => match buf.get(1) {
+            None => Decoded::Incomplete,
+            Some(b'[') => decode_csi(buf),
+            Some(b'O') => decode_ss3(buf),
+            // ESC ESC is never a sequence, the first one is the Esc key
+            Some(b'\x1b') => Decoded::Key(EditorKey::K(b'\x1b'), 1),
+            // terminals send alt+key as ESC followed by the key
+            Some(_) => match decode(&buf[1..]) {
+                Decoded::Key(key, len) => Decoded::Key(with_mods(Modifiers::ALT, key), len + 1),
+                Decoded::Ignore(len) => Decoded::Ignore(len + 1),
+                Decoded::Incomplete => Decoded::Incomplete,
+            },
+        },
+        b'\x7f' => Decoded::Key(EditorKey::Backspace, 1),
+        c if c < 0x80 => Decoded::Key(EditorKey::K(c), 1),
+        c => decode_utf8(buf, c),
+    }
+}
+
+fn decode_utf8(buf: &[u8], lead: u8) -> Decoded {
+    let len = match lead {
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => return Decoded::Key(EditorKey::K(lead), 1),
+    };
+    if buf.len() < len {
+        return if buf[1..].iter().all(|b| b & 0xc0 == 0x80) {
+            Decoded::Incomplete
+        } else {
+            Decoded::Key(EditorKey::K(lead), 1)
+        };
+    }
+    match std::str::from_utf8(&buf[..len]) {
+        Ok(s) => Decoded::Key(EditorKey::Char(s.chars().next().unwrap()), len),
+        Err(_) => Decoded::Key(EditorKey::K(lead), 1),
+    }
+}
+
+/// ESC [ params final, where params are digits separated by ';'
+fn decode_csi(buf: &[u8]) -> Decoded {
+    let mut i = 2;
+    while i < buf.len() && (0x30..0x40).contains(&buf[i]) {
+        i += 1;
+    }
+    // intermediate bytes, no key uses them
+    while i < buf.len() && (0x20..0x30).contains(&buf[i]) {
+        i += 1;
+    }
+    let Some(&last) = buf.get(i) else {
+        return Decoded::Incomplete;
+    };
+    let len = i + 1;
+    if !(0x40..0x7f).contains(&last) {
+        // not a well formed sequence, treat the ESC as a key of its own
+        return Decoded::Key(EditorKey::K(b'\x1b'), 1);
+    }
+    if buf[2] == b'<' {
+        return match decode_sgr_mouse(&buf[3..i], last) {
+            Some(ev) => Decoded::Key(EditorKey::Mouse(ev), len),
+            None => Decoded::Ignore(len),
+        };
+    }
+    let params: Vec<u32> = std::str::from_utf8(&buf[2..i])
+        .unwrap_or("")
+        .split(';')
+        .map(|p| p.parse().unwrap_or(0))
+        .collect();
+    let param = |n: usize| params.get(n).copied().unwrap_or(0);
+    let mods = Modifiers::from_param(param(1));
+    let key = match last {
+        b'A' => EditorKey::ArrowUp,
+        b'B' => EditorKey::ArrowDown,
+        b'C' => EditorKey::ArrowRight,
+        b'D' => EditorKey::ArrowLeft,
+        b'H' => EditorKey::HomeKey,
+        b'F' => EditorKey::EndKey,
+        b'P' => EditorKey::F(1),
+        b'Q' => EditorKey::F(2),
+        b'R' => EditorKey::F(3),
+        b'S' => EditorKey::F(4),
+        b'Z' => return Decoded::Key(with_mods(Modifiers::SHIFT, EditorKey::K(b'\t')), len),
+        b'~' => match param(0) {
+            1 | 7 => EditorKey::HomeKey,
+            2 => EditorKey::Insert,
+            3 => EditorKey::DelKey,
+            4 | 8 => EditorKey::EndKey,
+            5 => EditorKey::PageUp,
+            6 => EditorKey::PageDown,
+            n @ 11..=15 => EditorKey::F((n - 10) as u8),
+            n @ 17..=21 => EditorKey::F((n - 11) as u8),
+            n @ 23..=24 => EditorKey::F((n - 12) as u8),
+            _ => return Decoded::Ignore(len),
+        },
+        // CSI codepoint ; modifiers u, as sent by terminals that can
+        // report ctrl and alt on any key
+        b'u' => match char::from_u32(param(0)) {
+            // ctrl and a letter is the control char the terminal would
+            // have sent without this protocol
+            Some(c @ ('@'..='_' | 'a'..='z')) if mods.contains(Modifiers::CTRL) => {
+                let mods = Modifiers(mods.0 & !(Modifiers::CTRL.0 | Modifiers::SHIFT.0));
+                let key = EditorKey::K(c.to_ascii_uppercase() as u8 & 0x1f);
+                return Decoded::Key(with_mods(mods, key), len);
+            }
+            Some(c) if c.is_ascii() => EditorKey::K(c as u8),
+            Some(c) => EditorKey::Char(c),
+            None => return Decoded::Ignore(len),
+        },
+        _ => return Decoded::Ignore(len),
+    };
+    Decoded::Key(with_mods(mods, key), len)
+}
+
+/// SGR mouse reports, ESC [ < button ; column ; row M, or m on release
+fn decode_sgr_mouse(params: &[u8], last: u8) -> Option<MouseEvent> {
+    let params = std::str::from_utf8(params).ok()?;
+    let mut params = params.split(';').map(|p| p.parse::<usize>().ok());
+    let cb = params.next()??;
+    let col = params.next()??.checked_sub(1)?;
+    let row = params.next()??.checked_sub(1)?;
+    let mut mods = Modifiers::default();
+    if cb & 4 != 0 {
+        mods.0 |= Modifiers::SHIFT.0;
+    }
+    if cb & 8 != 0 {
+        mods.0 |= Modifiers::ALT.0;
+    }
+    if cb & 16 != 0 {
+        mods.0 |= Modifiers::CTRL.0;
+    }
+    let button = match cb & 3 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        _ => None,
+    };
+    let kind = if last == b'm' {
+        MouseKind::Release
+    } else if cb & 64 != 0 {
+        match cb & 3 {
+            0 => MouseKind::ScrollUp,
+            1 => MouseKind::ScrollDown,
+            _ => return None,
+        }
+    } else if cb & 32 != 0 {
+        MouseKind::Drag(button?)
+    } else {
+        MouseKind::Press(button?)
+    };
+    Some(MouseEvent {
+        kind,
+        row,
+        col,
+        mods,
+    })
+}
+
+/// ESC O final, what most terminals send for the arrows and F1 to F4 in
+/// application mode, optionally with a modifier digit before the final
+fn decode_ss3(buf: &[u8]) -> Decoded {
+    let mut i = 2;
+    while i < buf.len() && buf[i].is_ascii_digit() {
+        i += 1;
+    }
+    let Some(&last) = buf.get(i) else {
+        return Decoded::Incomplete;
+    };
+    let mods = std::str::from_utf8(&buf[2..i])
+        .ok()
+        .and_then(|p| p.parse().ok())
+        .map(Modifiers::from_param)
+        .unwrap_or_default();
+    let key = match last {
+        b'A' => EditorKey::ArrowUp,
+        b'B' => EditorKey::ArrowDown,
+        b'C' => EditorKey::ArrowRight,
+        b'D' => EditorKey::ArrowLeft,
+        b'H' => EditorKey::HomeKey,
+        b'F' => EditorKey::EndKey,
+        b'P' => EditorKey::F(1),
+        b'Q' => EditorKey::F(2),
+        b'R' => EditorKey::F(3),
+        b'S' => EditorKey::F(4),
+        _ => return Decoded::Key(with_mods(Modifiers::ALT, EditorKey::K(b'O')), 2),
+    };
+    Decoded::Key(with_mods(mods, key), i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the keys `reads` decode to, read by read, with a timeout after
+    /// the last one
+    fn keys(reads: &[&[u8]]) -> Vec<EditorKey> {
+        let mut input = InputDecoder::default();
+        let mut keys = Vec::new();
+        for bytes in reads {
+            input.push(bytes);
+            keys.extend(std::iter::from_fn(|| input.next_key(false)));
+        }
+        keys.extend(std::iter::from_fn(|| input.next_key(true)));
+        keys
+    }
+
+    fn with(mods: Modifiers, key: EditorKey) -> EditorKey {
+        EditorKey::Mod(mods, Box::new(key))
+    }
+
+    #[test]
+    fn csi_keys_and_their_modifiers() {
+        assert_eq!(
+            keys(&[b"\x1b[1;5C\x1b[A\x1b[3~\x1b[5;2~"]),
+            [
+                with(Modifiers::CTRL, EditorKey::ArrowRight),
+                EditorKey::ArrowUp,
+                EditorKey::DelKey,
+                with(Modifiers::SHIFT, EditorKey::PageUp),
+            ]
+        );
+        assert_eq!(
+            keys(&[b"\x1b[15~\x1b[17~\x1b[24~\x1b[1;3P"]),
+            [
+                EditorKey::F(5),
+                EditorKey::F(6),
+                EditorKey::F(12),
+                with(Modifiers::ALT, EditorKey::F(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn ss3_keys() {
+        assert_eq!(
+            keys(&[b"\x1bOA\x1bO5D\x1bOQ"]),
+            [
+                EditorKey::ArrowUp,
+                with(Modifiers::CTRL, EditorKey::ArrowLeft),
+                EditorKey::F(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn alt_is_esc_before_a_key() {
+        assert_eq!(
+            keys(&[b"\x1bx"]),
+            [with(Modifiers::ALT, EditorKey::K(b'x'))]
+        );
+        // apart, it's Esc and then the key
+        assert_eq!(keys(&[b"\x1b"]), [EditorKey::K(b'\x1b')]);
+        assert_eq!(
+            keys(&[b"\x1b\x1b[B"]),
+            [EditorKey::K(b'\x1b'), EditorKey::ArrowDown]
+        );
+    }
+
+    #[test]
+    fn ctrl_letters_from_csi_u_are_control_chars() {
+        assert_eq!(
+            keys(&[b"\x1b[99;5u\x1b[67;6u\x1b[99;7u\x1b[233u"]),
+            [
+                EditorKey::K(0x03),
+                EditorKey::K(0x03),
+                with(Modifiers::ALT, EditorKey::K(0x03)),
+                EditorKey::Char('é'),
+            ]
+        );
+    }
+
+    #[test]
+    fn utf8_split_across_reads() {
+        assert_eq!(
+            keys(&[b"a\xe6", b"\x97", b"\xa5b"]),
+            [
+                EditorKey::K(b'a'),
+                EditorKey::Char('日'),
+                EditorKey::K(b'b')
+            ]
+        );
+    }
+
+    #[test]
+    fn paste_end_split_across_reads() {
+        assert_eq!(
+            keys(&[b"\x1b[200~one\x1b", b"[20", b"1~x"]),
+            [EditorKey::Paste("one".to_string()), EditorKey::K(b'x')]
+        );
+        // a paste holding what looks like keys keeps them as text
+        assert_eq!(
+            keys(&[b"\x1b[200~\x1b[A\r", b"\x1b[201~"]),
+            [EditorKey::Paste("\x1b[A\r".to_string())]
+        );
+    }
+
+    #[test]
+    fn sgr_mouse_reports() {
+        let ev = |kind, row, col| MouseEvent {
+            kind,
+            row,
+            col,
+            mods: Modifiers::default(),
+        };
+        assert_eq!(
+            keys(&[b"\x1b[<0;3;2M\x1b[<32;4;2M\x1b[<0;4;2m\x1b[<65;1;1M"]),
+            [
+                EditorKey::Mouse(ev(MouseKind::Press(MouseButton::Left), 1, 2)),
+                EditorKey::Mouse(ev(MouseKind::Drag(MouseButton::Left), 1, 3)),
+                EditorKey::Mouse(ev(MouseKind::Release, 1, 3)),
+                EditorKey::Mouse(ev(MouseKind::ScrollDown, 0, 0)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,554 @@
+use crate::buffer::Buffer;
+use crate::window::Window;
+
+/// where a motion ends up in its line
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Col {
+    /// the column kept when moving up and down
+    Sticky,
+    At(usize),
+    /// the end of the line, and of every line moved to after it
+    End,
+}
+
+/// where a motion goes and how an operator treats the text in between
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Target {
+    pub line: usize,
+    pub col: Col,
+    /// whole lines are operated on
+    pub linewise: bool,
+    /// the char at the end is part of the text moved over
+    pub inclusive: bool,
+}
+
+impl Target {
+    fn exclusive(line: usize, col: usize) -> Self {
+        Self {
+            line,
+            col: Col::At(col),
+            linewise: false,
+            inclusive: false,
+        }
+    }
+
+    fn inclusive(line: usize, col: usize) -> Self {
+        Self {
+            inclusive: true,
+            ..Self::exclusive(line, col)
+        }
+    }
+
+    fn linewise(line: usize, col: Col) -> Self {
+        Self {
+            line,
+            col,
+            linewise: true,
+            inclusive: false,
+        }
+    }
+
+    /// the (line, col) the cursor of `win` would end up at
+    pub fn pos(&self, win: &Window, buf: &Buffer) -> (usize, usize) {
+        let len = buf.line_len(self.line);
+        let col = match self.col {
+            Col::Sticky if win.rightted => len,
+            Col::Sticky => win.max_x.min(len),
+            Col::At(col) => col,
+            Col::End => len,
+        };
+        (self.line, col)
+    }
+
+    /// puts the cursor of `win` there
+    pub fn apply(&self, win: &mut Window, buf: &Buffer) {
+        let (line, col) = self.pos(win, buf);
+        match self.col {
+            // the wanted column stays as it is
+            Col::Sticky => (win.cy, win.cx) = (line, col),
+            Col::At(_) => win.cursor_to(line, col),
+            Col::End => {
+                win.cy = line;
+                win.end(buf);
+            }
+        }
+    }
+}
+
+/// `f`, `F`, `t` and `T`, a search for a char in the cursor line
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Find {
+    pub ch: char,
+    pub forward: bool,
+    /// stop just before the char instead of on it
+    pub till: bool,
+    /// repeated with `;` or `,`, a till doesn't get stuck in front of the
+    /// char it stopped before last time
+    again: bool,
+}
+
+impl Find {
+    /// the find done by `;`, or by `,` when `reverse`
+    pub fn repeat(self, reverse: bool) -> Self {
+        Self {
+            forward: self.forward != reverse,
+            again: true,
+            ..self
+        }
+    }
+
+    fn target(&self, buf: &Buffer, line: usize, col: usize, count: usize) -> Option<Target> {
+        let text = buf.line(line);
+        let skip = usize::from(self.till && self.again);
+        if self.forward {
+            let found = text
+                .chars_at((col + 1 + skip).min(text.len_chars()))
+                .enumerate()
+                .filter(|&(_, c)| c == self.ch)
+                .nth(count - 1)?
+                .0;
+            let at = col + 1 + skip + found;
+            Some(Target::inclusive(line, if self.till { at - 1 } else { at }))
+        } else {
+            let found = text
+                .chars_at(col.saturating_sub(skip))
+                .reversed()
+                .enumerate()
+                .filter(|&(_, c)| c == self.ch)
+                .nth(count - 1)?
+                .0;
+            let at = col.checked_sub(skip + found + 1)?;
+            Some(Target::exclusive(line, if self.till { at + 1 } else { at }))
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `w` and `W`, true for WORDs
+    WordStart(bool),
+    /// `b` and `B`
+    WordBack(bool),
+    /// `e` and `E`
+    WordEnd(bool),
+    /// `ge` and `gE`
+    WordEndBack(bool),
+    /// `cw` and `cW` on a word, to the end of it without the blanks after
+    ChangeWord(bool),
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    LastNonBlank,
+    /// `gg`, the first line or line {count}
+    FirstLine,
+    /// `G`, the last line or line {count}
+    LastLine,
+    ScreenTop,
+    ScreenMiddle,
+    ScreenBottom,
+    Find(Find),
+}
+
+impl Motion {
+    /// the motion typed as `keys`
+    pub fn parse(keys: &str) -> Option<Self> {
+        let mut chars = keys.chars();
+        if let (Some(kind @ ('f' | 'F' | 't' | 'T')), Some(ch), None) =
+            (chars.next(), chars.next(), chars.next())
+        {
+            return Some(Motion::Find(Find {
+                ch,
+                forward: kind.is_lowercase(),
+                till: kind.eq_ignore_ascii_case(&'t'),
+                again: false,
+            }));
+        }
+        Some(match keys {
+            "h" | "\x08" => Motion::Left,
+            "l" | " " => Motion::Right,
+            "k" | "\x10" => Motion::Up,
+            "j" | "\x0e" | "\n" => Motion::Down,
+            "w" => Motion::WordStart(false),
+            "W" => Motion::WordStart(true),
+            "b" => Motion::WordBack(false),
+            "B" => Motion::WordBack(true),
+            "e" => Motion::WordEnd(false),
+            "E" => Motion::WordEnd(true),
+            "ge" => Motion::WordEndBack(false),
+            "gE" => Motion::WordEndBack(true),
+            "0" => Motion::LineStart,
+            "^" => Motion::FirstNonBlank,
+            "$" => Motion::LineEnd,
+            "g_" => Motion::LastNonBlank,
+            "gg" => Motion::FirstLine,
+            "G" => Motion::LastLine,
+            "H" => Motion::ScreenTop,
+            "M" => Motion::ScreenMiddle,
+            "L" => Motion::ScreenBottom,
+            _ => return None,
+        })
+    }
+
+    /// where the motion goes from the cursor of `win`, None when it can't
+    /// move at all. `operator` allows going one past the last char, so
+    /// that `dl` and `dw` reach the end of the line
+    pub fn target(
+        &self,
+        win: &Window,
+        buf: &Buffer,
+        count: Option<usize>,
+        operator: bool,
+    ) -> Option<Target> {
+        let n = count.unwrap_or(1);
+        let (cy, cx) = (win.cy, win.cx);
+        let len = buf.line_len(cy);
+        let last = buf.last_line();
+        Some(match self {
+            Motion::Left => {
+                if cx == 0 {
+                    return None;
+                }
+                Target::exclusive(cy, cx.saturating_sub(n))
+            }
+            Motion::Right => {
+                let max = if operator { len } else { len.saturating_sub(1) };
+                if cx >= max && operator {
+                    // `x` on an empty line deletes nothing, `s` still inserts
+                    return Some(Target::exclusive(cy, cx));
+                } else if cx >= max {
+                    return None;
+                }
+                Target::exclusive(cy, (cx + n).min(max))
+            }
+            Motion::Up => {
+                if cy == 0 {
+                    return None;
+                }
+                Target::linewise(cy.saturating_sub(n), Col::Sticky)
+            }
+            Motion::Down => {
+                if cy >= last {
+                    return None;
+                }
+                Target::linewise((cy + n).min(last), Col::Sticky)
+            }
+            Motion::WordStart(big) => {
+                let start = buf.pos_to_char(cy, cx);
+                let mut ix = start;
+                let mut from = start;
+                for _ in 0..n {
+                    from = ix;
+                    ix = next_word_start(buf, ix, *big);
+                }
+                let line = buf.char_to_line(from);
+                if operator && buf.char_to_line(ix) > line && class(buf, from, *big) != Class::Empty
+                {
+                    // `dw` on the last word of a line leaves the line break
+                    ix = buf.line_to_char(line) + buf.line_len(line);
+                }
+                if ix == buf.len_chars() && !operator {
+                    // the last word has no word after it, stop on its end
+                    let last = buf.last_line();
+                    ix = ix.min(buf.line_to_char(last) + buf.line_len(last).saturating_sub(1));
+                }
+                if ix == start {
+                    return None;
+                }
+                let (line, col) = buf.char_to_pos(ix);
+                Target::exclusive(line, col)
+            }
+            Motion::WordBack(big) => {
+                let start = buf.pos_to_char(cy, cx);
+                let mut ix = start;
+                for _ in 0..n {
+                    ix = prev_word_start(buf, ix, *big);
+                }
+                if ix == start {
+                    return None;
+                }
+                let (line, col) = buf.char_to_pos(ix);
+                Target::exclusive(line, col)
+            }
+            Motion::WordEnd(big) => {
+                let start = buf.pos_to_char(cy, cx);
+                let mut ix = start;
+                for _ in 0..n {
+                    ix = next_word_end(buf, ix, *big);
+                }
+                if ix == start {
+                    return None;
+                }
+                let (line, col) = buf.char_to_pos(ix);
+                Target::inclusive(line, col)
+            }
+            Motion::ChangeWord(big) => {
+                let start = buf.pos_to_char(cy, cx);
+                let c = class(buf, start, *big);
+                let mut ix = start;
+                while ix + 1 < buf.len_chars() && class(buf, ix + 1, *big) == c {
+                    ix += 1;
+                }
+                for _ in 1..n {
+                    ix = next_word_end(buf, ix, *big);
+                }
+                let (line, col) = buf.char_to_pos(ix);
+                Target::inclusive(line, col)
+            }
+            Motion::WordEndBack(big) => {
+                let start = buf.pos_to_char(cy, cx);
+                let mut ix = start;
+                for _ in 0..n {
+                    ix = prev_word_end(buf, ix, *big);
+                }
+                if ix == start {
+                    return None;
+                }
+                let (line, col) = buf.char_to_pos(ix);
+                Target::inclusive(line, col)
+            }
+            Motion::LineStart => Target::exclusive(cy, 0),
+            Motion::FirstNonBlank => Target::exclusive(cy, first_non_blank(buf, cy)),
+            Motion::LineEnd => {
+                let line = (cy + n - 1).min(last);
+                Target {
+                    col: Col::End,
+                    ..Target::inclusive(line, 0)
+                }
+            }
+            Motion::LastNonBlank => {
+                let line = (cy + n - 1).min(last);
+                let text = buf.line(line);
+                let blanks = text
+                    .chars_at(text.len_chars())
+                    .reversed()
+                    .take_while(|c| c.is_whitespace())
+                    .count();
+                let col = (text.len_chars() - blanks).saturating_sub(1);
+                Target::inclusive(line, col)
+            }
+            Motion::FirstLine => {
+                Target::linewise(count.map_or(0, |c| c - 1).min(last), Col::Sticky)
+            }
+            Motion::LastLine => {
+                Target::linewise(count.map_or(last, |c| c - 1).min(last), Col::Sticky)
+            }
+            Motion::ScreenTop => {
+                let line = (win.rowoff + n - 1).min(screen_last(win, buf));
+                Target::linewise(line, Col::Sticky)
+            }
+            Motion::ScreenMiddle => {
+                let line = (win.rowoff + screen_last(win, buf)) / 2;
+                Target::linewise(line, Col::Sticky)
+            }
+            Motion::ScreenBottom => {
+                let line = screen_last(win, buf).saturating_sub(n - 1).max(win.rowoff);
+                Target::linewise(line, Col::Sticky)
+            }
+            Motion::Find(find) => {
+                let target = find.target(buf, cy, cx, n)?;
+                if target.col == Col::At(cx) && !operator {
+                    // a till right before the char doesn't move, with an
+                    // operator it still takes the char under the cursor
+                    return None;
+                }
+                target
+            }
+        })
+    }
+}
+
+/// the last line shown in `win`
+fn screen_last(win: &Window, buf: &Buffer) -> usize {
+    (win.rowoff + win.height - 1).min(buf.last_line())
+}
+
+/// column of the first char in `line` that isn't a blank
+pub fn first_non_blank(buf: &Buffer, line: usize) -> usize {
+    let text = buf.line(line);
+    let blanks = text.chars().take_while(|c| c.is_whitespace()).count();
+    blanks.min(text.len_chars().saturating_sub(1))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Blank,
+    /// an empty line, which counts as a word of its own
+    Empty,
+    Punct,
+    Word,
+}
+
+fn at_line_start(buf: &Buffer, ix: usize) -> bool {
+    ix == 0 || buf.get_char(ix - 1) == Some('\n')
+}
+
+/// kind of the char at `ix`, for WORDs there is no difference between
+/// letters and punctuation
+fn class(buf: &Buffer, ix: usize, big: bool) -> Class {
+    match buf.get_char(ix) {
+        None | Some('\n') if at_line_start(buf, ix) => Class::Empty,
+        None => Class::Blank,
+        Some(c) if c.is_whitespace() => Class::Blank,
+        Some(_) if big => Class::Word,
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        Some(_) => Class::Punct,
+    }
+}
+
+fn next_word_start(buf: &Buffer, ix: usize, big: bool) -> usize {
+    let end = buf.len_chars();
+    let mut ix = ix;
+    match class(buf, ix, big) {
+        Class::Blank => {}
+        Class::Empty => ix += 1,
+        c => {
+            while ix < end && class(buf, ix, big) == c {
+                ix += 1;
+            }
+        }
+    }
+    while ix < end && class(buf, ix, big) == Class::Blank {
+        ix += 1;
+    }
+    ix.min(end)
+}
+
+fn prev_word_start(buf: &Buffer, ix: usize, big: bool) -> usize {
+    if ix == 0 {
+        return 0;
+    }
+    let mut ix = ix - 1;
+    while ix > 0 && class(buf, ix, big) == Class::Blank {
+        ix -= 1;
+    }
+    let c = class(buf, ix, big);
+    if c != Class::Empty {
+        while ix > 0 && class(buf, ix - 1, big) == c {
+            ix -= 1;
+        }
+    }
+    ix
+}
+
+fn next_word_end(buf: &Buffer, ix: usize, big: bool) -> usize {
+    // the newline ending the text is no place to stop at
+    let last = buf.last_line();
+    let end = buf.line_to_char(last) + buf.line_len(last);
+    let start = ix;
+    if ix + 1 >= end {
+        return ix;
+    }
+    let mut ix = ix + 1;
+    while ix + 1 < end && matches!(class(buf, ix, big), Class::Blank | Class::Empty) {
+        ix += 1;
+    }
+    let c = class(buf, ix, big);
+    if matches!(c, Class::Blank | Class::Empty) {
+        // only blanks after the last word
+        return start;
+    }
+    while ix + 1 < end && class(buf, ix + 1, big) == c {
+        ix += 1;
+    }
+    ix
+}
+
+fn prev_word_end(buf: &Buffer, ix: usize, big: bool) -> usize {
+    let start = ix;
+    let mut ix = ix;
+    match class(buf, ix, big) {
+        Class::Blank => {}
+        Class::Empty => ix = ix.saturating_sub(1),
+        c => {
+            while ix > 0 && class(buf, ix, big) == c {
+                ix -= 1;
+            }
+            if class(buf, ix, big) == c {
+                // in the first word, there is no end before it
+                return start;
+            }
+        }
+    }
+    while ix > 0 && class(buf, ix, big) == Class::Blank {
+        ix -= 1;
+    }
+    ix
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// where the cursor ends up after each motion in `keys` from
+    /// (line, col) of `text`
+    fn moves(text: &str, from: (usize, usize), keys: &[&str]) -> Vec<(usize, usize)> {
+        let buf = Buffer::new(text, PathBuf::from("test.txt"));
+        let mut win = Window::new(0);
+        win.height = 10;
+        win.cursor_to(from.0, from.1);
+        keys.iter()
+            .map(|keys| {
+                let motion = Motion::parse(keys).unwrap();
+                if let Some(target) = motion.target(&win, &buf, None, false) {
+                    target.apply(&mut win, &buf);
+                }
+                (win.cy, win.cx)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn line_motions_stop_at_the_last_line() {
+        assert_eq!(moves("one\ntwo\n", (0, 0), &["j", "j", "j"]), [(1, 0); 3]);
+        assert_eq!(
+            moves("one\ntwo\n", (0, 0), &["G", "L", "M"]),
+            [(1, 0), (1, 0), (0, 0)]
+        );
+        assert_eq!(moves("one\ntwo", (0, 0), &["G", "j"]), [(1, 0); 2]);
+        assert_eq!(moves("one\n\n", (0, 0), &["G", "j"]), [(1, 0); 2]);
+        assert_eq!(moves("", (0, 0), &["G", "j"]), [(0, 0); 2]);
+    }
+
+    #[test]
+    fn words_go_over_line_breaks() {
+        let text = "one two\n  three\n\nfour\n";
+        let w = moves(text, (0, 0), &["w", "w", "w", "w", "w"]);
+        assert_eq!(w, [(0, 4), (1, 2), (2, 0), (3, 0), (3, 3)]);
+        let b = moves(text, (3, 3), &["b", "b", "b", "b", "b", "b"]);
+        assert_eq!(b, [(3, 0), (2, 0), (1, 2), (0, 4), (0, 0), (0, 0)]);
+        let e = moves(text, (0, 0), &["e", "e", "e", "e", "e"]);
+        assert_eq!(e, [(0, 2), (0, 6), (1, 6), (3, 3), (3, 3)]);
+        let ge = moves(text, (3, 3), &["ge", "ge", "ge", "ge", "ge"]);
+        assert_eq!(ge, [(2, 0), (1, 6), (0, 6), (0, 2), (0, 2)]);
+    }
+
+    #[test]
+    fn words_stop_at_punctuation_and_big_words_do_not() {
+        let text = "a.b(c) d\ne,f\n";
+        let w = moves(text, (0, 0), &["w", "w", "w", "w", "w", "w"]);
+        assert_eq!(w, [(0, 1), (0, 2), (0, 3), (0, 4), (0, 5), (0, 7)]);
+        let big = moves(text, (0, 0), &["W", "W", "W"]);
+        assert_eq!(big, [(0, 7), (1, 0), (1, 2)]);
+        let e = moves(text, (0, 0), &["e", "e", "e", "E", "E"]);
+        assert_eq!(e, [(0, 1), (0, 2), (0, 3), (0, 5), (0, 7)]);
+        let big = moves(text, (1, 2), &["B", "B", "gE", "gE"]);
+        assert_eq!(big, [(1, 0), (0, 7), (0, 5), (0, 5)]);
+        let b = moves(text, (1, 2), &["b", "b", "ge", "ge"]);
+        assert_eq!(b, [(1, 1), (1, 0), (0, 7), (0, 5)]);
+    }
+
+    #[test]
+    fn finds_stay_in_the_line() {
+        let text = "a(b)c(d)\nx)\n";
+        let f = moves(text, (0, 0), &["f(", "f(", "f(", "F(", "F(", "fx"]);
+        assert_eq!(f, [(0, 1), (0, 5), (0, 5), (0, 1), (0, 1), (0, 1)]);
+        let t = moves(text, (0, 0), &["t)", "t)", "T(", "T(", "T("]);
+        assert_eq!(t, [(0, 2), (0, 2), (0, 2), (0, 2), (0, 2)]);
+    }
+}
@@ -0,0 +1,238 @@
+/// keys that act on the text a following motion moves over
+const OPERATORS: &[&str] = &["d", "c", "y", "<", ">", "=", "g~", "gu", "gU", "g?"];
+
+/// keys that are only the start of a command
+const PREFIXES: &[&str] = &["g", "z", "Z", "[", "]", "\x17"];
+
+/// keys that take any one char after them
+const TAKES_CHAR: &[&str] = &["f", "F", "t", "T", "r", "gr", "m", "'", "`", "q", "@"];
+
+/// a normal mode command as typed, `[count]["x][count]operator[count]motion`
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Command {
+    /// both counts multiplied, None when neither was given
+    pub count: Option<usize>,
+    pub register: Option<char>,
+    pub operator: Option<String>,
+    /// `v`, `V` or Ctrl-V typed after the operator, making the motion
+    /// charwise, linewise or blockwise
+    pub force: Option<char>,
+    /// the command, or after an operator the motion or text object. a
+    /// doubled operator like `dd` or `gUU` has the operator here too
+    pub keys: String,
+}
+
+impl Command {
+    /// the count, 1 when none was given
+    pub fn count1(&self) -> usize {
+        self.count.unwrap_or(1)
+    }
+}
+
+pub enum Step {
+    /// more keys are needed
+    Pending,
+    Done(Command),
+    /// the keys don't make a command, they are dropped
+    Invalid,
+}
+
+/// collects normal mode keys until they make a whole command
+#[derive(Debug, Default)]
+pub struct Parser {
+    count: Option<usize>,
+    /// count typed before the register, `2"a3x` deletes 6 chars
+    reg_count: Option<usize>,
+    register: Option<char>,
+    operator: Option<String>,
+    /// count typed after the operator
+    op_count: Option<usize>,
+    force: Option<char>,
+    keys: String,
+}
+
+impl Parser {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// true when no keys of a command have been typed
+    pub fn is_empty(&self) -> bool {
+        self.count.is_none()
+            && self.register.is_none()
+            && self.operator.is_none()
+            && self.keys.is_empty()
+    }
+
+    pub fn push(&mut self, c: char) -> Step {
+        self.push_key(c, false)
+    }
+
+    /// like `push`, for visual mode where operators act on the selection
+    /// right away and `i` and `a` start a text object
+    pub fn push_visual(&mut self, c: char) -> Step {
+        self.push_key(c, true)
+    }
+
+    fn push_key(&mut self, c: char, visual: bool) -> Step {
+        if self.keys.is_empty() {
+            let count = if self.operator.is_some() {
+                &mut self.op_count
+            } else {
+                &mut self.count
+            };
+            // 0 is a motion unless it continues a count
+            if let Some(d) = c.to_digit(10).filter(|&d| d != 0 || count.is_some()) {
+                *count = Some(
+                    count
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add(d as usize),
+                );
+                return Step::Pending;
+            }
+            if self.operator.is_some() && matches!(c, 'v' | 'V' | '\x16') {
+                self.force = Some(c);
+                return Step::Pending;
+            }
+        }
+        self.keys.push(c);
+        let keys = self.keys.as_str();
+        if let Some(reg) = keys.strip_prefix('"') {
+            if self.operator.is_some() || self.register.is_some() {
+                return self.invalid();
+            }
+            if let Some(reg) = reg.chars().next() {
+                self.register = Some(reg);
+                self.reg_count = self.count.take();
+                self.keys.clear();
+            }
+            return Step::Pending;
+        }
+        if PREFIXES.contains(&keys)
+            || TAKES_CHAR.contains(&keys)
+            || ((self.operator.is_some() || visual) && (keys == "i" || keys == "a"))
+        {
+            return Step::Pending;
+        }
+        match &self.operator {
+            None if !visual && OPERATORS.contains(&keys) => {
+                self.operator = Some(std::mem::take(&mut self.keys));
+                Step::Pending
+            }
+            Some(op) => {
+                // dd, gUU and gUgU all work on whole lines
+                let last = &op[op.len() - 1..];
+                if keys == op || keys == last {
+                    self.keys = op.clone();
+                } else if OPERATORS.contains(&keys) {
+                    return self.invalid();
+                }
+                self.done()
+            }
+            None => self.done(),
+        }
+    }
+
+    fn done(&mut self) -> Step {
+        let parser = std::mem::take(self);
+        let counts = [parser.reg_count, parser.count, parser.op_count];
+        let count = counts
+            .into_iter()
+            .flatten()
+            .reduce(|a, b| a.saturating_mul(b));
+        Step::Done(Command {
+            count,
+            register: parser.register,
+            operator: parser.operator,
+            force: parser.force,
+            keys: parser.keys,
+        })
+    }
+
+    fn invalid(&mut self) -> Step {
+        self.clear();
+        Step::Invalid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the command `keys` make, None while more are needed
+    fn parse(keys: &str) -> Option<Command> {
+        let mut parser = Parser::default();
+        let mut last = None;
+        for c in keys.chars() {
+            last = match parser.push(c) {
+                Step::Pending => None,
+                Step::Done(cmd) => Some(cmd),
+                Step::Invalid => panic!("{keys:?} is invalid"),
+            };
+        }
+        last
+    }
+
+    fn command(
+        count: Option<usize>,
+        register: Option<char>,
+        operator: &str,
+        keys: &str,
+    ) -> Command {
+        Command {
+            count,
+            register,
+            operator: (!operator.is_empty()).then(|| operator.to_string()),
+            force: None,
+            keys: keys.to_string(),
+        }
+    }
+
+    #[test]
+    fn counts_before_and_after_the_operator_multiply() {
+        assert_eq!(parse("3dd"), Some(command(Some(3), None, "d", "d")));
+        assert_eq!(parse("d3w"), Some(command(Some(3), None, "d", "w")));
+        assert_eq!(parse("2d3w"), Some(command(Some(6), None, "d", "w")));
+        assert_eq!(parse("dw"), Some(command(None, None, "d", "w")));
+    }
+
+    #[test]
+    fn a_count_may_come_before_and_after_the_register() {
+        assert_eq!(parse("\"a3yy"), Some(command(Some(3), Some('a'), "y", "y")));
+        assert_eq!(parse("2\"a3x"), Some(command(Some(6), Some('a'), "", "x")));
+        assert_eq!(
+            parse("2\"ad3w"),
+            Some(command(Some(6), Some('a'), "d", "w"))
+        );
+        assert_eq!(
+            parse("2\"a3d4w"),
+            Some(command(Some(24), Some('a'), "d", "w"))
+        );
+        assert_eq!(parse("2\"ap"), Some(command(Some(2), Some('a'), "", "p")));
+    }
+
+    #[test]
+    fn zero_is_a_motion_unless_it_continues_a_count() {
+        assert_eq!(parse("0"), Some(command(None, None, "", "0")));
+        assert_eq!(parse("10j"), Some(command(Some(10), None, "", "j")));
+        assert_eq!(parse("d0"), Some(command(None, None, "d", "0")));
+        assert_eq!(parse("d10j"), Some(command(Some(10), None, "d", "j")));
+    }
+
+    #[test]
+    fn operators_can_be_forced_and_doubled() {
+        let mut cmd = command(None, None, "d", "j");
+        cmd.force = Some('v');
+        assert_eq!(parse("dvj"), Some(cmd));
+        assert_eq!(parse("gUU"), Some(command(None, None, "gU", "gU")));
+        assert_eq!(parse("gUgU"), Some(command(None, None, "gU", "gU")));
+        assert_eq!(parse("dfx"), Some(command(None, None, "d", "fx")));
+        assert_eq!(parse("ci("), Some(command(None, None, "c", "i(")));
+        assert_eq!(parse("d"), None);
+        let mut parser = Parser::default();
+        parser.push('d');
+        assert!(matches!(parser.push('"'), Step::Invalid));
+        assert!(parser.is_empty());
+    }
+}
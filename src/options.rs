@@ -0,0 +1,152 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// settings changed with `:set`
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// milliseconds to wait for the rest of an escape sequence before
+    /// taking ESC as the Esc key
+    pub ttimeoutlen: u64,
+    /// clicking, dragging and scrolling with the mouse
+    pub mouse: bool,
+    /// columns an indent step of `>>` and `<<` takes
+    pub shiftwidth: u64,
+    /// columns a tab takes up
+    pub tabstop: u64,
+    /// indent with spaces instead of tabs
+    pub expandtab: bool,
+    /// keep the undo history of a file in `undodir` when it is written,
+    /// and read it back when the file is opened again
+    pub undofile: bool,
+    /// where undo files go, "." puts them next to the files
+    pub undodir: String,
+    /// "unnamedplus" makes yanks, deletes and puts without a register
+    /// use the system clipboard, "unnamed" the primary selection
+    pub clipboard: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            ttimeoutlen: 50,
+            mouse: true,
+            shiftwidth: 8,
+            tabstop: 8,
+            expandtab: false,
+            undofile: false,
+            undodir: "~/.local/state/ri/undo".to_string(),
+            clipboard: String::new(),
+        }
+    }
+}
+
+impl Options {
+    /// the register yanks, deletes and puts use when none is named,
+    /// besides the unnamed one, as 'clipboard' says
+    pub fn clipboard_register(&self) -> Option<char> {
+        let has = |name| self.clipboard.split(',').any(|v| v == name);
+        if has("unnamedplus") {
+            Some('+')
+        } else if has("unnamed") {
+            Some('*')
+        } else {
+            None
+        }
+    }
+
+    /// `undodir` with a leading `~` put in as the home directory
+    pub fn undo_dir(&self) -> PathBuf {
+        match (self.undodir.strip_prefix("~/"), env::var_os("HOME")) {
+            (Some(rest), Some(home)) => Path::new(&home).join(rest),
+            _ => PathBuf::from(&self.undodir),
+        }
+    }
+
+    /// applies one `:set` argument, `name=value` sets and `name` or
+    /// `name?` returns the current value to show. boolean options are
+    /// switched with `name`, `noname`, `invname` and `name!`
+    pub fn set(&mut self, arg: &str) -> Result<Option<String>, String> {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg, None),
+        };
+        let query = name.ends_with('?');
+        let name = name.trim_end_matches('?');
+        if let Some(flag) = self.flag(name) {
+            if value.is_some() {
+                return Err(format!("E474: Invalid argument: {}", arg));
+            }
+            if query {
+                let prefix = if *flag { "" } else { "no" };
+                return Ok(Some(format!("{}{}", prefix, name)));
+            }
+            *flag = true;
+            return Ok(None);
+        }
+        if let Some(name) = name.strip_suffix('!') {
+            let flag = self.flag(name).ok_or_else(|| unknown(name))?;
+            *flag = !*flag;
+            return Ok(None);
+        }
+        for (prefix, on) in [("no", false), ("inv", true)] {
+            if let Some(flag) = name.strip_prefix(prefix).and_then(|n| self.flag(n)) {
+                *flag = if on { !*flag } else { false };
+                return Ok(None);
+            }
+        }
+        match name {
+            "ttimeoutlen" | "ttm" => match value {
+                Some(value) if !query => self.ttimeoutlen = number(arg, value)?,
+                _ => return Ok(Some(format!("ttimeoutlen={}", self.ttimeoutlen))),
+            },
+            "shiftwidth" | "sw" => match value {
+                Some(value) if !query => self.shiftwidth = number(arg, value)?,
+                _ => return Ok(Some(format!("shiftwidth={}", self.shiftwidth))),
+            },
+            "tabstop" | "ts" => match value {
+                Some(value) if !query => {
+                    self.tabstop = number(arg, value)?;
+                    if self.tabstop == 0 {
+                        return Err(format!("E487: Argument must be positive: {}", arg));
+                    }
+                }
+                _ => return Ok(Some(format!("tabstop={}", self.tabstop))),
+            },
+            "clipboard" | "cb" => match value {
+                Some(value) if !query => {
+                    let valid = |v: &str| matches!(v, "" | "unnamed" | "unnamedplus");
+                    if !value.split(',').all(valid) {
+                        return Err(format!("E474: Invalid argument: {}", arg));
+                    }
+                    self.clipboard = value.to_string();
+                }
+                _ => return Ok(Some(format!("clipboard={}", self.clipboard))),
+            },
+            "undodir" | "udir" => match value {
+                Some(value) if !query => self.undodir = value.to_string(),
+                _ => return Ok(Some(format!("undodir={}", self.undodir))),
+            },
+            _ => return Err(unknown(name)),
+        }
+        Ok(None)
+    }
+
+    fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "mouse" => Some(&mut self.mouse),
+            "expandtab" | "et" => Some(&mut self.expandtab),
+            "undofile" | "udf" => Some(&mut self.undofile),
+            _ => None,
+        }
+    }
+}
+
+fn unknown(name: &str) -> String {
+    format!("E518: Unknown option: {}", name)
+}
+
+fn number(arg: &str, value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("E521: Number required after =: {}", arg))
+}
@@ -1,9 +1,31 @@
-use rustix::{io::Errno, stdio, termios::*};
+use rustix::{
+    io::{self, Errno},
+    stdio,
+    termios::*,
+};
+use signal_hook::{consts::SIGTSTP, low_level::emulate_default_handler};
 use std::ops::IndexMut;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 
-pub fn enable_raw_mode<'a>() -> Result<Termios, Errno> {
+/// whether the terminal is in raw mode, so that restoring it from both
+/// the panic hook and a drop only happens once
+static RAW: AtomicBool = AtomicBool::new(false);
+
+/// the terminal settings from before raw mode was first entered, put back
+/// whenever the editor gives the terminal away
+static ORIG_TERMIOS: OnceLock<Termios> = OnceLock::new();
+
+/// puts the terminal into raw mode, again after a suspend too
+pub fn enable_raw_mode() -> Result<(), Errno> {
     let fd = stdio::stdin();
-    let orig_termios = tcgetattr(fd)?;
+    let orig_termios = match ORIG_TERMIOS.get() {
+        Some(termios) => termios,
+        None => {
+            let termios = tcgetattr(fd)?;
+            ORIG_TERMIOS.get_or_init(|| termios)
+        }
+    };
     let mut raw = orig_termios.clone();
     raw.input_modes &= !(InputModes::BRKINT
         | InputModes::ICRNL
@@ -17,16 +39,48 @@ pub fn enable_raw_mode<'a>() -> Result<Termios, Errno> {
     *raw.special_codes.index_mut(SpecialCodeIndex::VMIN) = 0;
     *raw.special_codes.index_mut(SpecialCodeIndex::VTIME) = 1;
     tcsetattr(fd, OptionalActions::Flush, &raw)?;
-    Ok(orig_termios)
+    // draw on the alternate screen so the shell's scrollback survives,
+    // have pastes wrapped in ESC[200~ and ESC[201~, and mouse events
+    // reported as SGR sequences once they are turned on
+    io::write(stdio::stdout(), b"\x1b[?1049h\x1b[?2004h\x1b[?1006h")?;
+    RAW.store(true, Ordering::SeqCst);
+    Ok(())
 }
 
-pub fn disable_raw_mode<'a>(old_termios: &Termios) {
+pub fn disable_raw_mode() {
+    if !RAW.swap(false, Ordering::SeqCst) {
+        return;
+    }
     let fd = stdio::stdin();
-    if let Ok(_) = tcsetattr(fd, OptionalActions::Flush, &old_termios) {
-        println!("bye!");
+    set_mouse_reporting(false);
+    let _ = io::write(stdio::stdout(), b"\x1b[?1006l\x1b[?2004l\x1b[?1049l");
+    if let Some(orig_termios) = ORIG_TERMIOS.get() {
+        let _ = tcsetattr(fd, OptionalActions::Flush, orig_termios);
     }
 }
 
-pub fn clear_screen() {
-    print!("\x1b[2J");
+/// hands the terminal back to the shell and stops the process, returns
+/// once it is continued with the terminal still in cooked mode
+pub fn suspend() -> std::io::Result<()> {
+    disable_raw_mode();
+    emulate_default_handler(SIGTSTP)
+}
+
+/// restores the terminal before the panic message is printed on a panic
+pub fn restore_on_panic() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        disable_raw_mode();
+        default_hook(info);
+    }));
+}
+
+/// reports of clicks, drags and the wheel
+pub fn set_mouse_reporting(on: bool) {
+    let seq: &[u8] = if on {
+        b"\x1b[?1000h\x1b[?1002h"
+    } else {
+        b"\x1b[?1002l\x1b[?1000l"
+    };
+    let _ = io::write(stdio::stdout(), seq);
 }
@@ -0,0 +1,337 @@
+use crate::input::EditorKey;
+
+/// how text yanked into a register is put back
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    /// inside a line, at the cursor
+    Charwise,
+    /// as whole lines, above or below the cursor line
+    Linewise,
+    /// as a rectangle, each line of the text going into the next line
+    Blockwise,
+}
+
+/// text yanked or deleted, for putting back with `p` and `P`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Register {
+    /// linewise text ends with a line break, blockwise text has one line
+    /// of the block per line
+    pub text: String,
+    pub kind: Kind,
+    /// the keys of a recorded macro as they were typed, `text` has the
+    /// ones that are chars
+    keys: Option<Vec<EditorKey>>,
+}
+
+impl Register {
+    pub fn new(text: String, kind: Kind) -> Self {
+        Self {
+            text,
+            kind,
+            keys: None,
+        }
+    }
+
+    /// a macro recorded with `q`
+    pub fn recorded(keys: Vec<EditorKey>) -> Self {
+        let text = keys
+            .iter()
+            .filter_map(|key| match key {
+                EditorKey::K(b) => Some(String::from(*b as char)),
+                EditorKey::Char(c) => Some(String::from(*c)),
+                EditorKey::Paste(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect();
+        Self {
+            text,
+            kind: Kind::Charwise,
+            keys: Some(keys),
+        }
+    }
+
+    /// the keys `@` replays, those recorded or else the text typed
+    pub fn keys(&self) -> Vec<EditorKey> {
+        if let Some(keys) = &self.keys {
+            return keys.clone();
+        }
+        self.text
+            .chars()
+            .map(|c| {
+                if c.is_ascii() {
+                    EditorKey::K(c as u8)
+                } else {
+                    EditorKey::Char(c)
+                }
+            })
+            .collect()
+    }
+
+    /// the lines of a block, or of linewise text without the last line
+    /// break
+    pub fn lines(&self) -> Vec<&str> {
+        let text = match self.kind {
+            Kind::Linewise => self.text.strip_suffix('\n').unwrap_or(&self.text),
+            _ => &self.text,
+        };
+        text.split('\n').collect()
+    }
+
+    /// `self` with `more` added to the end. text that ends up with whole
+    /// lines on either side is linewise
+    fn append(self, more: Register) -> Register {
+        let kind = match (self.kind, more.kind) {
+            (Kind::Charwise, Kind::Charwise) => Kind::Charwise,
+            (Kind::Blockwise, Kind::Blockwise) => Kind::Blockwise,
+            _ => Kind::Linewise,
+        };
+        let sep = match self.kind {
+            Kind::Linewise => "",
+            _ if kind == Kind::Charwise => "",
+            _ => "\n",
+        };
+        if self.keys.is_some() || more.keys.is_some() {
+            // appending to a macro with `qA`
+            let mut keys = self.keys();
+            keys.extend(more.keys());
+            return Register::recorded(keys);
+        }
+        let mut text = format!("{}{}{}", self.text, sep, more.text);
+        if kind == Kind::Linewise && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        Register::new(text, kind)
+    }
+}
+
+/// where yanked, deleted and other remembered text is kept, by the name
+/// typed after `"`
+#[derive(Debug, Default)]
+pub struct Registers {
+    /// `""`, what was yanked or deleted last
+    unnamed: Option<Register>,
+    /// `"0` for the last yank, `"1` to `"9` for deletes, newest first
+    numbered: [Option<Register>; 10],
+    /// `"a` to `"z`
+    named: [Option<Register>; 26],
+    /// `"-`, the last delete within a line
+    small: Option<Register>,
+    /// `".`, the text typed in the last insert
+    inserted: Option<String>,
+    /// `":`, the last command line run
+    command: Option<String>,
+    /// `"/`, the last search pattern
+    search: Option<String>,
+    /// `"+` and `"*`, what was last put on the system clipboard and the
+    /// primary selection
+    clipboard: [Option<Register>; 2],
+}
+
+impl Registers {
+    /// true for the registers a yank or delete can go to
+    pub fn writable(name: char) -> bool {
+        matches!(name, '"' | '-' | '_' | '+' | '*') || name.is_ascii_alphanumeric()
+    }
+
+    /// a yank into register `name`, `"0` when none was named
+    pub fn yank(&mut self, name: Option<char>, register: Register) {
+        match name {
+            None | Some('"') => {
+                self.numbered[0] = Some(register.clone());
+                self.unnamed = Some(register);
+            }
+            Some(name) => self.store(name, register),
+        }
+    }
+
+    /// a delete into register `name`. when none was named, text of more
+    /// than a line pushes `"1` to `"9` down and goes into `"1`, less goes
+    /// into `"-`
+    pub fn delete(&mut self, name: Option<char>, register: Register) {
+        match name {
+            None | Some('"') => {
+                if register.kind == Kind::Charwise && !register.text.contains('\n') {
+                    self.small = Some(register.clone());
+                } else {
+                    self.numbered[1..].rotate_right(1);
+                    self.numbered[1] = Some(register.clone());
+                }
+                self.unnamed = Some(register);
+            }
+            Some(name) => self.store(name, register),
+        }
+    }
+
+    /// puts `register` into the one named and the unnamed one
+    fn store(&mut self, name: char, register: Register) {
+        if let Some(register) = self.set(name, register) {
+            self.unnamed = Some(register);
+        }
+    }
+
+    /// puts `register` into the one named, an uppercase letter appends to
+    /// the lowercase one. returns what the register holds then, None for
+    /// the black hole and names that can't be written
+    pub fn set(&mut self, name: char, register: Register) -> Option<Register> {
+        let slot = match name {
+            '"' => &mut self.unnamed,
+            '-' => &mut self.small,
+            '+' => &mut self.clipboard[0],
+            '*' => &mut self.clipboard[1],
+            '0'..='9' => &mut self.numbered[name as usize - '0' as usize],
+            'a'..='z' | 'A'..='Z' => {
+                &mut self.named[name.to_ascii_lowercase() as usize - 'a' as usize]
+            }
+            _ => return None,
+        };
+        let register = match slot.take() {
+            Some(old) if name.is_ascii_uppercase() => old.append(register),
+            _ => register,
+        };
+        *slot = Some(register.clone());
+        Some(register)
+    }
+
+    pub fn set_inserted(&mut self, text: &str) {
+        self.inserted = Some(text.to_string());
+    }
+
+    pub fn set_command(&mut self, cmd: &str) {
+        self.command = Some(cmd.to_string());
+    }
+
+    /// what register `name` holds, the ones that keep a string hold it
+    /// charwise
+    pub fn get(&self, name: char) -> Option<Register> {
+        let text = |s: &Option<String>| s.clone().map(|s| Register::new(s, Kind::Charwise));
+        match name {
+            '"' => self.unnamed.clone(),
+            '-' => self.small.clone(),
+            '+' => self.clipboard[0].clone(),
+            '*' => self.clipboard[1].clone(),
+            '0'..='9' => self.numbered[name as usize - '0' as usize].clone(),
+            'a'..='z' | 'A'..='Z' => {
+                self.named[name.to_ascii_lowercase() as usize - 'a' as usize].clone()
+            }
+            '.' => text(&self.inserted),
+            ':' => text(&self.command),
+            '/' => text(&self.search),
+            _ => None,
+        }
+    }
+
+    /// the registers that hold something, in the order `:registers`
+    /// lists them, `"%` holds the name of the current file
+    pub fn list(&self, file: &str) -> Vec<(char, Register)> {
+        let names = "\"0123456789abcdefghijklmnopqrstuvwxyz-.:%/+*";
+        names
+            .chars()
+            .filter_map(|name| match name {
+                '%' => Some((name, Register::new(file.to_string(), Kind::Charwise))),
+                _ => Some((name, self.get(name)?)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Register {
+        Register::new(text.to_string(), Kind::Charwise)
+    }
+
+    fn lines(text: &str) -> Register {
+        Register::new(text.to_string(), Kind::Linewise)
+    }
+
+    fn text(registers: &Registers, name: char) -> Option<String> {
+        registers.get(name).map(|r| r.text)
+    }
+
+    #[test]
+    fn deletes_of_lines_shift_the_numbered_registers() {
+        let mut registers = Registers::default();
+        for n in 1..=10 {
+            registers.delete(None, lines(&format!("{}\n", n)));
+        }
+        assert_eq!(text(&registers, '1').as_deref(), Some("10\n"));
+        assert_eq!(text(&registers, '9').as_deref(), Some("2\n"));
+        assert_eq!(text(&registers, '"').as_deref(), Some("10\n"));
+        // a yank goes to "0 and leaves the deletes where they are
+        registers.yank(None, chars("y"));
+        assert_eq!(text(&registers, '0').as_deref(), Some("y"));
+        assert_eq!(text(&registers, '1').as_deref(), Some("10\n"));
+        // text over more than a line counts as a big delete too
+        registers.delete(None, chars("a\nb"));
+        assert_eq!(text(&registers, '1').as_deref(), Some("a\nb"));
+        assert_eq!(text(&registers, '2').as_deref(), Some("10\n"));
+    }
+
+    #[test]
+    fn small_deletes_go_only_to_the_small_register() {
+        let mut registers = Registers::default();
+        registers.delete(None, lines("line\n"));
+        registers.delete(None, chars("word"));
+        assert_eq!(text(&registers, '-').as_deref(), Some("word"));
+        assert_eq!(text(&registers, '"').as_deref(), Some("word"));
+        assert_eq!(text(&registers, '1').as_deref(), Some("line\n"));
+        assert_eq!(text(&registers, '2'), None);
+        // named, it goes there and nowhere else
+        registers.delete(Some('a'), chars("x"));
+        assert_eq!(text(&registers, 'a').as_deref(), Some("x"));
+        assert_eq!(text(&registers, '-').as_deref(), Some("word"));
+        assert_eq!(text(&registers, '"').as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn the_black_hole_keeps_nothing() {
+        let mut registers = Registers::default();
+        registers.yank(None, chars("kept"));
+        registers.delete(Some('_'), lines("gone\n"));
+        registers.yank(Some('_'), chars("gone"));
+        assert_eq!(text(&registers, '"').as_deref(), Some("kept"));
+        assert_eq!(text(&registers, '1'), None);
+        assert_eq!(registers.get('_'), None);
+    }
+
+    #[test]
+    fn uppercase_appends_and_combines_kinds() {
+        let mut registers = Registers::default();
+        registers.yank(Some('a'), chars("one"));
+        registers.yank(Some('A'), chars(" two"));
+        assert_eq!(registers.get('a'), Some(chars("one two")));
+        // lines added to text make it linewise
+        registers.yank(Some('A'), lines("three\n"));
+        assert_eq!(registers.get('a'), Some(lines("one two\nthree\n")));
+        registers.yank(Some('A'), chars("four"));
+        assert_eq!(registers.get('a'), Some(lines("one two\nthree\nfour\n")));
+        let block = |text: &str| Register::new(text.to_string(), Kind::Blockwise);
+        registers.yank(Some('b'), block("ab\ncd"));
+        registers.yank(Some('B'), block("ef"));
+        assert_eq!(registers.get('b'), Some(block("ab\ncd\nef")));
+        // uppercase on an empty register is a plain yank
+        registers.yank(Some('C'), chars("c"));
+        assert_eq!(registers.get('c'), Some(chars("c")));
+        assert_eq!(text(&registers, '"').as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn some_registers_can_only_be_read() {
+        let mut registers = Registers::default();
+        for name in ['.', ':', '/', '%', '#', '='] {
+            assert!(!Registers::writable(name), "{name}");
+            assert_eq!(registers.set(name, chars("x")), None);
+        }
+        assert_eq!(registers.get('.'), None);
+        registers.set_inserted("typed");
+        registers.set_command("w");
+        assert_eq!(registers.get('.'), Some(chars("typed")));
+        assert_eq!(registers.get(':'), Some(chars("w")));
+        let list = registers.list("file.txt");
+        let names: String = list.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ".:%");
+        assert_eq!(list[2].1, chars("file.txt"));
+    }
+}
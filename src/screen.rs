@@ -0,0 +1,452 @@
+use std::fmt::Write;
+
+use crate::{bg_color, fg_color, NEUTRAL_COLOR};
+
+type Rgb = (u8, u8, u8);
+
+/// colors of one cell, None is the terminal's default
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Style {
+    pub fg: Option<Rgb>,
+    pub bg: Option<Rgb>,
+}
+
+impl Style {
+    pub const PLAIN: Style = Style { fg: None, bg: None };
+
+    pub const fn fg(self, r: u8, g: u8, b: u8) -> Self {
+        Self {
+            fg: Some((r, g, b)),
+            ..self
+        }
+    }
+
+    pub const fn bg(self, r: u8, g: u8, b: u8) -> Self {
+        Self {
+            bg: Some((r, g, b)),
+            ..self
+        }
+    }
+}
+
+pub const TEXT: Style = Style::PLAIN.bg(250, 238, 209).fg(0, 0, 0);
+pub const SELECTION: Style = Style::PLAIN.bg(214, 191, 140).fg(0, 0, 0);
+pub const GUTTER: Style = Style::PLAIN.bg(96, 115, 116);
+pub const STATUS: Style = Style::PLAIN.bg(178, 165, 155);
+pub const ERROR: Style = STATUS.fg(160, 0, 0);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Cell {
+    pub ch: char,
+    pub style: Style,
+}
+
+impl Cell {
+    /// the right half of a wide char, drawn by the cell before it
+    pub const CONTINUATION: Cell = Cell {
+        ch: '\0',
+        style: Style::PLAIN,
+    };
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            style: Style::PLAIN,
+        }
+    }
+}
+
+/// what the whole screen should look like, drawn into before every
+/// refresh and then compared against the frame shown before
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub rows: usize,
+    pub cols: usize,
+    cells: Vec<Cell>,
+    /// 0 based (row, col) of the cursor, hidden when None
+    pub cursor: Option<(usize, usize)>,
+    /// text for the terminal to put on the clipboard, or on the primary
+    /// selection when the flag is set
+    pub clipboard: Option<(String, bool)>,
+}
+
+impl Frame {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self {
+            rows,
+            cols,
+            cells: vec![Cell::default(); rows * cols],
+            cursor: None,
+            clipboard: None,
+        }
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Cell {
+        self.cells[row * self.cols + col]
+    }
+
+    /// writes `text` from (row, col) on, cut off at the right edge,
+    /// returns the column after the last char written
+    pub fn put(
+        &mut self,
+        row: usize,
+        col: usize,
+        text: impl IntoIterator<Item = char>,
+        style: Style,
+    ) -> usize {
+        if row >= self.rows {
+            return col;
+        }
+        let mut col = col;
+        for ch in text {
+            if col >= self.cols {
+                break;
+            }
+            // control chars would move the terminal's cursor somewhere else
+            let ch = if ch.is_control() { ' ' } else { ch };
+            if width(ch) == 2 {
+                if col + 1 >= self.cols {
+                    // half of it doesn't fit
+                    self.set(row, col, Cell { ch: ' ', style });
+                    col += 1;
+                    continue;
+                }
+                self.set(row, col, Cell { ch, style });
+                self.set(row, col + 1, Cell::CONTINUATION);
+                col += 2;
+            } else {
+                self.set(row, col, Cell { ch, style });
+                col += 1;
+            }
+        }
+        col
+    }
+
+    /// writes one cell, blanking what is left of a wide char it covers
+    /// half of
+    fn set(&mut self, row: usize, col: usize, cell: Cell) {
+        let at = row * self.cols + col;
+        if self.cells[at] == Cell::CONTINUATION && cell != Cell::CONTINUATION {
+            self.cells[at - 1].ch = ' ';
+        }
+        if col + 1 < self.cols && self.cells[at + 1] == Cell::CONTINUATION {
+            self.cells[at + 1] = Cell {
+                ch: ' ',
+                style: self.cells[at].style,
+            };
+        }
+        self.cells[at] = cell;
+    }
+
+    /// blanks the columns from `from` up to `to` (exclusive)
+    pub fn fill(&mut self, row: usize, from: usize, to: usize, style: Style) {
+        let n = to.min(self.cols).saturating_sub(from);
+        self.put(row, from, std::iter::repeat_n(' ', n), style);
+    }
+
+    /// changes the colors of the columns from `from` up to `to`
+    /// (exclusive), keeping their text
+    pub fn restyle(&mut self, row: usize, from: usize, to: usize, style: Style) {
+        if row >= self.rows {
+            return;
+        }
+        for col in from..to.min(self.cols) {
+            let cell = &mut self.cells[row * self.cols + col];
+            if *cell != Cell::CONTINUATION {
+                cell.style = style;
+            }
+        }
+    }
+}
+
+/// turns frames into terminal output, sending only the cells that differ
+/// from the previous frame
+#[derive(Debug, Default)]
+pub struct Screen {
+    prev: Option<Frame>,
+}
+
+impl Screen {
+    /// forgets what is on the terminal, the next frame is drawn in full
+    pub fn invalidate(&mut self) {
+        self.prev = None;
+    }
+
+    pub fn render(&mut self, frame: Frame) -> String {
+        let prev = self
+            .prev
+            .take()
+            .filter(|p| p.rows == frame.rows && p.cols == frame.cols);
+        // synchronized output, the terminal shows the frame only once
+        // all of it has arrived
+        let mut out = String::from("\x1b[?2026h\x1b[?25l");
+        if let Some((text, primary)) = &frame.clipboard {
+            // OSC 52, which reaches the clipboard of the terminal even
+            // over ssh
+            let which = if *primary { 'p' } else { 'c' };
+            let _ = write!(out, "\x1b]52;{};{}\x07", which, base64(text.as_bytes()));
+        }
+        if prev.is_none() {
+            out.push_str(NEUTRAL_COLOR);
+            out.push_str("\x1b[2J");
+        }
+        let mut pos = None;
+        let mut style = Style::PLAIN;
+        for row in 0..frame.rows {
+            for col in 0..frame.cols {
+                let cell = frame.cell(row, col);
+                if cell == Cell::CONTINUATION {
+                    // already drawn along with the char before it
+                    continue;
+                }
+                let width =
+                    if frame.cols > col + 1 && frame.cell(row, col + 1) == Cell::CONTINUATION {
+                        2
+                    } else {
+                        1
+                    };
+                let unchanged = match &prev {
+                    Some(prev) => {
+                        (col..col + width).all(|c| prev.cell(row, c) == frame.cell(row, c))
+                    }
+                    None => cell == Cell::default(),
+                };
+                if unchanged {
+                    continue;
+                }
+                if pos != Some((row, col)) {
+                    move_to(&mut out, pos, row, col);
+                }
+                if cell.style != style {
+                    set_style(&mut out, style, cell.style);
+                    style = cell.style;
+                }
+                out.push(cell.ch);
+                // writing the last column leaves the cursor in a pending
+                // wrap state, so don't rely on where it is afterwards
+                pos = (col + width < frame.cols).then_some((row, col + width));
+            }
+        }
+        if style != Style::PLAIN {
+            out.push_str(NEUTRAL_COLOR);
+        }
+        if let Some((row, col)) = frame.cursor {
+            if pos != Some((row, col)) {
+                move_to(&mut out, None, row, col);
+            }
+            out.push_str("\x1b[?25h");
+        }
+        out.push_str("\x1b[?2026l");
+        self.prev = Some(frame);
+        out
+    }
+}
+
+/// columns `c` takes up on the terminal, 2 for the wide chars of east
+/// asian scripts and emoji
+pub fn width(c: char) -> usize {
+    let wide = matches!(c as u32,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd);
+    if wide {
+        2
+    } else {
+        1
+    }
+}
+
+/// the shortest sequence that moves the cursor from `from` to (row, col)
+fn move_to(out: &mut String, from: Option<(usize, usize)>, row: usize, col: usize) {
+    match from {
+        Some((r, c)) if r == row && c < col => {
+            let _ = write!(out, "\x1b[{}C", col - c);
+        }
+        Some((r, _)) if r + 1 == row && col == 0 => out.push_str("\r\n"),
+        Some((r, _)) if r == row => {
+            let _ = write!(out, "\x1b[{}G", col + 1);
+        }
+        _ => {
+            let _ = write!(out, "\x1b[{};{}H", row + 1, col + 1);
+        }
+    }
+}
+
+/// switches the colors from `from` to `to`, touching only what changed
+fn set_style(out: &mut String, from: Style, to: Style) {
+    let mut from = from;
+    if (from.fg.is_some() && to.fg.is_none()) || (from.bg.is_some() && to.bg.is_none()) {
+        out.push_str(NEUTRAL_COLOR);
+        from = Style::PLAIN;
+    }
+    if let (Some((r, g, b)), true) = (to.fg, to.fg != from.fg) {
+        out.push_str(&fg_color(r, g, b));
+    }
+    if let (Some((r, g, b)), true) = (to.bg, to.bg != from.bg) {
+        out.push_str(&bg_color(r, g, b));
+    }
+}
+
+/// `bytes` in base64, padded
+fn base64(bytes: &[u8]) -> String {
+    const DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(DIGITS[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "\x1b[?2026h\x1b[?25l";
+    const END: &str = "\x1b[?2026l";
+
+    fn frame(rows: &[&str]) -> Frame {
+        let mut frame = Frame::new(rows.len(), 6);
+        for (row, text) in rows.iter().enumerate() {
+            frame.put(row, 0, text.chars(), Style::PLAIN);
+        }
+        frame
+    }
+
+    #[test]
+    fn the_first_frame_is_drawn_in_full() {
+        let mut screen = Screen::default();
+        let out = screen.render(frame(&["ab", "", " c"]));
+        assert_eq!(
+            out,
+            format!("{START}{NEUTRAL_COLOR}\x1b[2J\x1b[1;1Hab\x1b[3;2Hc{END}")
+        );
+    }
+
+    #[test]
+    fn only_changed_cells_are_sent() {
+        let mut screen = Screen::default();
+        screen.render(frame(&["abcdef", "ghijkl"]));
+        assert_eq!(
+            screen.render(frame(&["abcdef", "ghijkl"])),
+            format!("{START}{END}")
+        );
+        let out = screen.render(frame(&["aXcdYf", "Zhijkl"]));
+        // forward on the same row, then onto the start of the next one
+        assert_eq!(out, format!("{START}\x1b[1;2HX\x1b[2CY\r\nZ{END}"));
+        let out = screen.render(frame(&["aXcdYf", "ZhiQkl"]));
+        assert_eq!(out, format!("{START}\x1b[2;4HQ{END}"));
+    }
+
+    #[test]
+    fn the_cursor_is_moved_by_the_shortest_sequence() {
+        let mut screen = Screen::default();
+        screen.render(frame(&["abcdef"]));
+        let mut next = frame(&["abcdeX"]);
+        next.cursor = Some((0, 1));
+        // the last column leaves the cursor in a pending wrap, so it is
+        // placed absolutely afterwards
+        let out = screen.render(next);
+        assert_eq!(out, format!("{START}\x1b[1;6HX\x1b[1;2H\x1b[?25h{END}"));
+        let moves = [
+            (Some((0, 1)), 0, 4, "\x1b[3C"),
+            (Some((0, 4)), 0, 1, "\x1b[2G"),
+            (Some((0, 4)), 1, 0, "\r\n"),
+            (Some((0, 4)), 1, 2, "\x1b[2;3H"),
+            (None, 0, 0, "\x1b[1;1H"),
+        ];
+        for (from, row, col, expected) in moves {
+            let mut out = String::new();
+            move_to(&mut out, from, row, col);
+            assert_eq!(out, expected, "{from:?} to {row},{col}");
+        }
+    }
+
+    #[test]
+    fn colors_are_reset_when_one_goes_away() {
+        let mut screen = Screen::default();
+        let mut next = Frame::new(1, 4);
+        next.put(0, 0, "ab".chars(), TEXT);
+        next.put(0, 2, "c".chars(), Style::PLAIN.fg(1, 2, 3));
+        next.put(0, 3, "d".chars(), Style::PLAIN);
+        let out = screen.render(next);
+        let expected = format!(
+            "{START}{NEUTRAL_COLOR}\x1b[2J\x1b[1;1H{}{}ab{NEUTRAL_COLOR}{}c{NEUTRAL_COLOR}d{END}",
+            fg_color(0, 0, 0),
+            bg_color(250, 238, 209),
+            fg_color(1, 2, 3),
+        );
+        assert_eq!(out, expected);
+        // still colored at the end, reset so the shell isn't
+        let mut next = Frame::new(1, 4);
+        next.put(0, 0, "abcd".chars(), TEXT);
+        let out = screen.render(next);
+        assert!(out.ends_with(&format!("{NEUTRAL_COLOR}{END}")), "{out:?}");
+    }
+
+    #[test]
+    fn everything_is_drawn_again_after_invalidate() {
+        let mut screen = Screen::default();
+        screen.render(frame(&["ab"]));
+        screen.invalidate();
+        let out = screen.render(frame(&["ab"]));
+        assert_eq!(
+            out,
+            format!("{START}{NEUTRAL_COLOR}\x1b[2J\x1b[1;1Hab{END}")
+        );
+        // a new size can't be compared against the old frame either
+        let out = screen.render(frame(&["ab", "c"]));
+        assert_eq!(
+            out,
+            format!("{START}{NEUTRAL_COLOR}\x1b[2J\x1b[1;1Hab\r\nc{END}")
+        );
+    }
+
+    #[test]
+    fn wide_chars_take_two_cells() {
+        let mut screen = Screen::default();
+        let next = frame(&["日本x"]);
+        assert_eq!(next.cell(0, 0).ch, '日');
+        assert_eq!(next.cell(0, 1), Cell::CONTINUATION);
+        assert_eq!(next.cell(0, 4).ch, 'x');
+        let out = screen.render(next);
+        assert_eq!(
+            out,
+            format!("{START}{NEUTRAL_COLOR}\x1b[2J\x1b[1;1H日本x{END}")
+        );
+        // the cursor stands after the two columns of the char
+        let out = screen.render(frame(&["日本y"]));
+        assert_eq!(out, format!("{START}\x1b[1;5Hy{END}"));
+        // covering half of one blanks the other half
+        let mut next = frame(&["日本y"]);
+        next.put(0, 1, "a".chars(), Style::PLAIN);
+        let out = screen.render(next);
+        assert_eq!(out, format!("{START}\x1b[1;1H a{END}"));
+        // one that doesn't fit at the right edge is left out
+        let mut next = Frame::new(1, 3);
+        assert_eq!(next.put(0, 0, "ab日".chars(), Style::PLAIN), 3);
+        assert_eq!(next.cell(0, 2).ch, ' ');
+    }
+}
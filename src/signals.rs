@@ -0,0 +1,74 @@
+use std::io::{self, Read};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use signal_hook::consts::{SIGCONT, SIGTSTP, SIGWINCH};
+use signal_hook::{flag, low_level::pipe};
+
+/// signals the editor reacts to. handlers only set a flag and write to a
+/// self-pipe, so the main loop can wait on the pipe together with stdin
+/// and do the actual work outside of the handler
+#[derive(Debug)]
+pub struct Signals {
+    wake: UnixStream,
+    resized: Arc<AtomicBool>,
+    continued: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Signals {
+    pub fn new() -> io::Result<Self> {
+        let (wake, write) = UnixStream::pair()?;
+        wake.set_nonblocking(true)?;
+        write.set_nonblocking(true)?;
+        let resized = Arc::new(AtomicBool::new(false));
+        let continued = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+        flag::register(SIGWINCH, Arc::clone(&resized))?;
+        flag::register(SIGCONT, Arc::clone(&continued))?;
+        // a SIGTSTP from outside is taken like Ctrl-Z, so the terminal is
+        // restored before the process actually stops
+        flag::register(SIGTSTP, Arc::clone(&stopped))?;
+        for signal in [SIGWINCH, SIGCONT, SIGTSTP] {
+            pipe::register(signal, write.try_clone()?)?;
+        }
+        Ok(Self {
+            wake,
+            resized,
+            continued,
+            stopped,
+        })
+    }
+
+    /// readable whenever a signal came in
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.wake.as_fd()
+    }
+
+    /// empties the pipe so that polling it blocks again
+    pub fn drain(&self) {
+        let mut buf = [0u8; 64];
+        while let Ok(n) = (&self.wake).read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+        }
+    }
+
+    /// true once after each change of the terminal size
+    pub fn take_resized(&self) -> bool {
+        self.resized.swap(false, Ordering::SeqCst)
+    }
+
+    /// true once after the process was continued from a stop
+    pub fn take_continued(&self) -> bool {
+        self.continued.swap(false, Ordering::SeqCst)
+    }
+
+    /// true once after someone asked the process to stop
+    pub fn take_stopped(&self) -> bool {
+        self.stopped.swap(false, Ordering::SeqCst)
+    }
+}
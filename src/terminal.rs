@@ -0,0 +1,330 @@
+#[cfg(test)]
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use rustix::{
+    event::{self, PollFd, PollFlags},
+    fd::BorrowedFd,
+    io::{self, Errno},
+    stdio,
+    termios::tcgetwinsize,
+};
+
+use crate::raw::{self, disable_raw_mode, enable_raw_mode, restore_on_panic, set_mouse_reporting};
+use crate::screen::{Frame, Screen};
+use crate::signals::Signals;
+
+/// what ended a wait on the terminal
+#[derive(Clone, PartialEq, Debug)]
+pub enum Event {
+    /// bytes typed or pasted, decoded into keys by the editor
+    Input(Vec<u8>),
+    /// the size changed
+    Resize,
+    /// something outside asked the editor to stop, like Ctrl-Z does
+    Stop,
+    /// the process was continued after a stop
+    Continue,
+    /// nothing came in within the timeout
+    Timeout,
+    /// there will be no more input
+    Eof,
+}
+
+/// everything the editor needs from the terminal it runs in
+pub trait Terminal: std::fmt::Debug {
+    /// waits up to `timeout_ms` (forever when negative) for the next event
+    fn read(&mut self, timeout_ms: i32) -> Result<Event, Errno>;
+    /// shows a frame
+    fn draw(&mut self, frame: Frame) -> Result<(), Errno>;
+    /// rows and columns, None when they can't be found out
+    fn size(&mut self) -> Option<(u16, u16)>;
+    fn enter_raw(&mut self) -> Result<(), Errno>;
+    fn leave_raw(&mut self);
+    /// reports of clicks, drags and the wheel
+    fn set_mouse(&mut self, on: bool);
+    /// leaves raw mode and stops the editor until it is continued
+    fn suspend(&mut self) -> std::io::Result<()>;
+}
+
+/// the terminal on stdin and stdout, put back the way it was on drop
+#[derive(Debug)]
+pub struct Tty {
+    stdout: BorrowedFd<'static>,
+    stdin: BorrowedFd<'static>,
+    signals: Signals,
+    screen: Screen,
+}
+
+impl Tty {
+    pub fn new() -> std::io::Result<Self> {
+        let signals = Signals::new()?;
+        restore_on_panic();
+        Ok(Self {
+            stdout: stdio::stdout(),
+            stdin: stdio::stdin(),
+            signals,
+            screen: Screen::default(),
+        })
+    }
+
+    /// the signal that came in, oldest concern first
+    fn signal(&self) -> Option<Event> {
+        if self.signals.take_stopped() {
+            Some(Event::Stop)
+        } else if self.signals.take_continued() {
+            Some(Event::Continue)
+        } else if self.signals.take_resized() {
+            Some(Event::Resize)
+        } else {
+            None
+        }
+    }
+
+    /// asks the terminal for its size by moving the cursor to the bottom
+    /// right corner and reading back where it ended up
+    fn get_cursor_position(&mut self) -> Result<(u16, u16), Errno> {
+        io::write(self.stdout, "\x1b[999C\x1b[999B\x1b[6n".as_bytes())?;
+        let mut buf = [0u8; 32];
+        io::read(self.stdin, &mut buf)?;
+        let mut rows = 0;
+        let mut cols = 0;
+        let mut i = 0;
+        while i < buf.len() && buf[i] != b'[' {
+            i += 1;
+        }
+        i += 1;
+        while i < buf.len() && buf[i].is_ascii_digit() {
+            rows = rows * 10 + (buf[i] - b'0') as u16;
+            i += 1;
+        }
+        i += 1;
+        while i < buf.len() && buf[i].is_ascii_digit() {
+            cols = cols * 10 + (buf[i] - b'0') as u16;
+            i += 1;
+        }
+        if i >= buf.len() || buf[i] != b'R' || rows == 0 || cols == 0 {
+            return Err(Errno::INVAL);
+        }
+        Ok((rows, cols))
+    }
+}
+
+impl Drop for Tty {
+    fn drop(&mut self) {
+        disable_raw_mode();
+    }
+}
+
+impl Terminal for Tty {
+    fn read(&mut self, timeout_ms: i32) -> Result<Event, Errno> {
+        if let Some(event) = self.signal() {
+            return Ok(event);
+        }
+        let mut fds = [
+            PollFd::new(&self.stdin, PollFlags::IN),
+            PollFd::from_borrowed_fd(self.signals.fd(), PollFlags::IN),
+        ];
+        match event::poll(&mut fds, timeout_ms) {
+            Ok(0) => return Ok(Event::Timeout),
+            Ok(_) => {}
+            Err(Errno::INTR) => return Ok(self.signal().unwrap_or(Event::Timeout)),
+            Err(e) => return Err(e),
+        }
+        if !fds[1].revents().is_empty() {
+            self.signals.drain();
+            return Ok(self.signal().unwrap_or(Event::Timeout));
+        }
+        let mut buf = [0u8; 1024];
+        let n = io::read(self.stdin, &mut buf)?;
+        Ok(if n > 0 {
+            Event::Input(buf[..n].to_vec())
+        } else {
+            // readable but empty, the terminal hung up
+            Event::Eof
+        })
+    }
+
+    fn draw(&mut self, frame: Frame) -> Result<(), Errno> {
+        let out = self.screen.render(frame);
+        let mut out = out.as_bytes();
+        while !out.is_empty() {
+            let n = io::write(self.stdout, out)?;
+            out = &out[n..];
+        }
+        Ok(())
+    }
+
+    fn size(&mut self) -> Option<(u16, u16)> {
+        match tcgetwinsize(self.stdout) {
+            Ok(winsize) if winsize.ws_row != 0 && winsize.ws_col != 0 => {
+                Some((winsize.ws_row, winsize.ws_col))
+            }
+            _ => self.get_cursor_position().ok(),
+        }
+    }
+
+    fn enter_raw(&mut self) -> Result<(), Errno> {
+        enable_raw_mode()?;
+        // back on a fresh alternate screen, nothing of the last frame is there
+        self.screen.invalidate();
+        Ok(())
+    }
+
+    fn leave_raw(&mut self) {
+        disable_raw_mode();
+    }
+
+    fn set_mouse(&mut self, on: bool) {
+        set_mouse_reporting(on);
+    }
+
+    fn suspend(&mut self) -> std::io::Result<()> {
+        raw::suspend()?;
+        // the SIGCONT that woke us up is dealt with by the caller
+        self.signals.take_continued();
+        Ok(())
+    }
+}
+
+/// an in-memory terminal that keeps the last frame drawn, for driving
+/// the editor without a tty. clones share the same screen, so one can be
+/// kept to feed keys and look at the result while the editor owns another
+#[cfg(test)]
+#[derive(Clone, Debug)]
+pub struct Headless {
+    state: Rc<RefCell<VirtualScreen>>,
+}
+
+#[cfg(test)]
+#[derive(Debug)]
+struct VirtualScreen {
+    rows: u16,
+    cols: u16,
+    events: VecDeque<Event>,
+    frame: Option<Frame>,
+    raw: bool,
+    mouse: bool,
+    suspended: usize,
+}
+
+#[cfg(test)]
+impl Headless {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(VirtualScreen {
+                rows,
+                cols,
+                events: VecDeque::new(),
+                frame: None,
+                raw: false,
+                mouse: false,
+                suspended: 0,
+            })),
+        }
+    }
+
+    /// queues bytes as if they were typed, one read returns one call's worth
+    pub fn feed(&self, bytes: &[u8]) {
+        let mut state = self.state.borrow_mut();
+        state.events.push_back(Event::Input(bytes.to_vec()));
+    }
+
+    /// queues a wait that runs out, which ends a pending escape sequence
+    pub fn pause(&self) {
+        self.state.borrow_mut().events.push_back(Event::Timeout);
+    }
+
+    pub fn resize(&self, rows: u16, cols: u16) {
+        let mut state = self.state.borrow_mut();
+        state.rows = rows;
+        state.cols = cols;
+        state.events.push_back(Event::Resize);
+    }
+
+    /// text of a screen row, with trailing blanks removed
+    pub fn row(&self, row: usize) -> String {
+        let state = self.state.borrow();
+        let Some(frame) = &state.frame else {
+            return String::new();
+        };
+        if row >= frame.rows {
+            return String::new();
+        }
+        let text: String = (0..frame.cols)
+            .map(|col| frame.cell(row, col).ch)
+            .filter(|&ch| ch != '\0')
+            .collect();
+        text.trim_end().to_string()
+    }
+
+    /// every row of the screen
+    pub fn text(&self) -> Vec<String> {
+        let rows = self.state.borrow().frame.as_ref().map_or(0, |f| f.rows);
+        (0..rows).map(|row| self.row(row)).collect()
+    }
+
+    /// the last frame drawn
+    pub fn frame(&self) -> Option<Frame> {
+        self.state.borrow().frame.clone()
+    }
+
+    /// 0 based (row, col) of the cursor, None when it's hidden
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        self.state.borrow().frame.as_ref().and_then(|f| f.cursor)
+    }
+
+    pub fn is_raw(&self) -> bool {
+        self.state.borrow().raw
+    }
+
+    pub fn mouse(&self) -> bool {
+        self.state.borrow().mouse
+    }
+
+    /// how often the editor suspended itself
+    pub fn suspended(&self) -> usize {
+        self.state.borrow().suspended
+    }
+}
+
+#[cfg(test)]
+impl Terminal for Headless {
+    fn read(&mut self, _timeout_ms: i32) -> Result<Event, Errno> {
+        Ok(self
+            .state
+            .borrow_mut()
+            .events
+            .pop_front()
+            .unwrap_or(Event::Eof))
+    }
+
+    fn draw(&mut self, frame: Frame) -> Result<(), Errno> {
+        self.state.borrow_mut().frame = Some(frame);
+        Ok(())
+    }
+
+    fn size(&mut self) -> Option<(u16, u16)> {
+        let state = self.state.borrow();
+        Some((state.rows, state.cols))
+    }
+
+    fn enter_raw(&mut self) -> Result<(), Errno> {
+        self.state.borrow_mut().raw = true;
+        Ok(())
+    }
+
+    fn leave_raw(&mut self) {
+        self.state.borrow_mut().raw = false;
+    }
+
+    fn set_mouse(&mut self, on: bool) {
+        self.state.borrow_mut().mouse = on;
+    }
+
+    fn suspend(&mut self) -> std::io::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.raw = false;
+        state.suspended += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,570 @@
+use crate::buffer::Buffer;
+
+/// the text a text object like `iw` or `a(` covers
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Object {
+    /// chars `start..end` of the buffer
+    pub start: usize,
+    pub end: usize,
+    /// whole lines, `start` and `end` are at the start of a line
+    pub linewise: bool,
+}
+
+impl Object {
+    fn chars(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            linewise: false,
+        }
+    }
+}
+
+/// the object typed as `keys` around the char at `at`. a count takes that
+/// many words, sentences or paragraphs, or goes that many levels of
+/// brackets or tags out
+pub fn select(buf: &Buffer, at: usize, keys: &str, count: usize) -> Option<Object> {
+    let mut chars = keys.chars();
+    let (Some(kind @ ('i' | 'a')), Some(c), None) = (chars.next(), chars.next(), chars.next())
+    else {
+        return None;
+    };
+    let inner = kind == 'i';
+    match c {
+        'w' | 'W' => word(buf, at, count, inner, c == 'W'),
+        's' => sentence(buf, at, count, inner),
+        'p' => paragraph(buf, at, count, inner),
+        '"' | '\'' | '`' => quote(buf, at, c, inner),
+        '(' | ')' | 'b' => bracket(buf, at, ('(', ')'), count, inner),
+        '[' | ']' => bracket(buf, at, ('[', ']'), count, inner),
+        '{' | '}' | 'B' => bracket(buf, at, ('{', '}'), count, inner),
+        '<' | '>' => bracket(buf, at, ('<', '>'), count, inner),
+        't' => tag(buf, at, count, inner),
+        _ => None,
+    }
+}
+
+/// a run of text, `blank` for the white space between words, sentences
+/// or paragraphs
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    start: usize,
+    end: usize,
+    blank: bool,
+}
+
+/// the spans an inner or around object at `at` takes. inner counts blanks
+/// as spans of their own, around takes `count` non-blank spans and the
+/// blank after them, or the one before when there is none after
+fn pick(spans: &[Span], at: usize, count: usize, inner: bool) -> Option<(usize, usize)> {
+    let i = spans.iter().position(|s| at >= s.start && at < s.end)?;
+    if inner {
+        let last = spans.get(i + count - 1)?;
+        return Some((spans[i].start, last.end));
+    }
+    let mut j = i;
+    let mut taken = usize::from(!spans[i].blank);
+    while taken < count {
+        j += 1;
+        if !spans.get(j)?.blank {
+            taken += 1;
+        }
+    }
+    if spans[i].blank {
+        return Some((spans[i].start, spans[j].end));
+    }
+    match spans.get(j + 1) {
+        Some(next) if next.blank => Some((spans[i].start, next.end)),
+        _ => {
+            let start = match i.checked_sub(1).map(|k| spans[k]) {
+                Some(prev) if prev.blank => prev.start,
+                _ => spans[i].start,
+            };
+            Some((start, spans[j].end))
+        }
+    }
+}
+
+/// splits `chars` starting at buffer offset `offset` into runs of the
+/// same class
+fn runs(chars: &[char], offset: usize, class: impl Fn(char) -> u8) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let k = class(c);
+        match spans.last_mut() {
+            Some(last) if class(chars[last.start - offset]) == k => last.end += 1,
+            _ => spans.push(Span {
+                start: offset + i,
+                end: offset + i + 1,
+                blank: k == 0,
+            }),
+        }
+    }
+    spans
+}
+
+/// `iw`, `aw`, `iW` and `aW`, in the cursor line
+fn word(buf: &Buffer, at: usize, count: usize, inner: bool, big: bool) -> Option<Object> {
+    let line = buf.char_to_line(at);
+    let start = buf.line_to_char(line);
+    let chars: Vec<char> = buf.line(line).chars().collect();
+    let spans = runs(&chars, start, |c| match c {
+        c if c.is_whitespace() => 0,
+        _ if big => 1,
+        c if c.is_alphanumeric() || c == '_' => 1,
+        _ => 2,
+    });
+    let (start, end) = pick(&spans, at, count, inner)?;
+    Some(Object::chars(start, end))
+}
+
+/// the first and last line of the paragraph `line` is in, None on an
+/// empty line
+fn paragraph_lines(buf: &Buffer, line: usize) -> Option<(usize, usize)> {
+    if buf.line_len(line) == 0 {
+        return None;
+    }
+    let mut first = line;
+    while first > 0 && buf.line_len(first - 1) > 0 {
+        first -= 1;
+    }
+    let mut last = line;
+    while last + 1 < buf.line_count() && buf.line_len(last + 1) > 0 {
+        last += 1;
+    }
+    Some((first, last))
+}
+
+/// `is` and `as`, in the paragraph the cursor is in. a sentence ends at a
+/// `.`, `!` or `?` followed by blanks or the end of the line, closing
+/// brackets and quotes after it are part of it
+fn sentence(buf: &Buffer, at: usize, count: usize, inner: bool) -> Option<Object> {
+    let (first, last) = paragraph_lines(buf, buf.char_to_line(at))?;
+    let start = buf.line_to_char(first);
+    let end = buf.line_to_char(last) + buf.line_len(last);
+    let chars: Vec<char> = buf.slice(start..end).chars().collect();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let from = i;
+        let blank = chars[i].is_whitespace();
+        if blank {
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+        } else {
+            while i < chars.len() {
+                let c = chars[i];
+                i += 1;
+                if matches!(c, '.' | '!' | '?') {
+                    while i < chars.len() && matches!(chars[i], ')' | ']' | '"' | '\'') {
+                        i += 1;
+                    }
+                    if i == chars.len() || chars[i].is_whitespace() {
+                        break;
+                    }
+                }
+            }
+        }
+        spans.push(Span {
+            start: start + from,
+            end: start + i,
+            blank,
+        });
+    }
+    let (start, end) = pick(&spans, at, count, inner)?;
+    Some(Object::chars(start, end))
+}
+
+/// `ip` and `ap`, paragraphs are separated by empty lines, which make up
+/// the blank spans
+fn paragraph(buf: &Buffer, at: usize, count: usize, inner: bool) -> Option<Object> {
+    let mut lines = buf.line_count();
+    if lines > 1 && buf.line_len(lines - 1) == 0 {
+        // the empty line after a trailing line break isn't really there
+        lines -= 1;
+    }
+    let mut spans: Vec<Span> = Vec::new();
+    for line in 0..lines {
+        let blank = buf.line_len(line) == 0;
+        match spans.last_mut() {
+            Some(last) if last.blank == blank => last.end += 1,
+            _ => spans.push(Span {
+                start: line,
+                end: line + 1,
+                blank,
+            }),
+        }
+    }
+    let (first, end) = pick(&spans, buf.char_to_line(at), count, inner)?;
+    let end = if end < buf.line_count() {
+        buf.line_to_char(end)
+    } else {
+        buf.len_chars()
+    };
+    Some(Object {
+        start: buf.line_to_char(first),
+        end,
+        linewise: true,
+    })
+}
+
+/// `i"`, `a"` and the like for `'` and `` ` ``, in the cursor line.
+/// quotes pair up from the start of the line, a quote after a backslash
+/// doesn't count. when the cursor isn't in a quoted string the next one
+/// in the line is used
+fn quote(buf: &Buffer, at: usize, q: char, inner: bool) -> Option<Object> {
+    let line = buf.char_to_line(at);
+    let start = buf.line_to_char(line);
+    let chars: Vec<char> = buf.line(line).chars().collect();
+    let col = at - start;
+    let quotes: Vec<usize> = (0..chars.len())
+        .filter(|&i| chars[i] == q && (i == 0 || chars[i - 1] != '\\'))
+        .collect();
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| col <= close)?;
+    if inner {
+        return Some(Object::chars(start + open + 1, start + close));
+    }
+    let blank = |i: usize| chars.get(i).is_some_and(|c| c.is_whitespace());
+    let mut end = close + 1;
+    while blank(end) {
+        end += 1;
+    }
+    let mut from = open;
+    if end == close + 1 {
+        while from > 0 && blank(from - 1) {
+            from -= 1;
+        }
+    }
+    Some(Object::chars(start + from, start + end))
+}
+
+/// `i(`, `a{` and the other brackets. the inner part of a block whose
+/// brackets are on lines of their own is the lines in between
+fn bracket(
+    buf: &Buffer,
+    at: usize,
+    (open, close): (char, char),
+    count: usize,
+    inner: bool,
+) -> Option<Object> {
+    // a closing bracket under the cursor belongs to the pair it closes
+    let mut i = if buf.get_char(at) == Some(close) {
+        at.checked_sub(1)?
+    } else {
+        at
+    };
+    let mut depth = 0;
+    let mut levels = count;
+    let from = loop {
+        match buf.get_char(i) {
+            Some(c) if c == close => depth += 1,
+            Some(c) if c == open && depth == 0 => {
+                levels -= 1;
+                if levels == 0 {
+                    break i;
+                }
+            }
+            Some(c) if c == open => depth -= 1,
+            _ => {}
+        }
+        i = i.checked_sub(1)?;
+    };
+    let mut depth = 0;
+    let mut to = from + 1;
+    loop {
+        match buf.get_char(to)? {
+            c if c == open => depth += 1,
+            c if c == close && depth == 0 => break,
+            c if c == close => depth -= 1,
+            _ => {}
+        }
+        to += 1;
+    }
+    if !inner {
+        return Some(Object::chars(from, to + 1));
+    }
+    let mut start = from + 1;
+    let mut end = to;
+    let line_start = buf.line_to_char(buf.char_to_line(to));
+    let own_line = buf.get_char(start) == Some('\n');
+    if own_line {
+        start += 1;
+    }
+    // the indent before the closing bracket stays
+    let alone = start < line_start && buf.slice(line_start..to).chars().all(|c| c.is_whitespace());
+    if alone {
+        end = line_start;
+    }
+    Some(Object {
+        start,
+        end,
+        linewise: own_line && alone,
+    })
+}
+
+/// true when the text at char `at` starts with `s`
+fn starts_with(buf: &Buffer, at: usize, s: &str) -> bool {
+    let end = at + s.chars().count();
+    end <= buf.len_chars() && buf.slice(at..end) == s
+}
+
+/// the tag starting with the `<` at `start` as (name, end, closing),
+/// None for comments, declarations and tags that close themselves
+fn tag_at(buf: &Buffer, start: usize) -> Option<(String, usize, bool)> {
+    let mut inside = String::new();
+    let mut end = start + 1;
+    loop {
+        match buf.get_char(end)? {
+            '>' => break,
+            '<' => return None,
+            c => inside.push(c),
+        }
+        end += 1;
+    }
+    let (body, closing) = match inside.strip_prefix('/') {
+        Some(body) => (body.trim(), true),
+        None => (inside.as_str(), false),
+    };
+    if body.is_empty() || body.starts_with(['!', '?']) || (!closing && body.ends_with('/')) {
+        return None;
+    }
+    let name = body.split(|c: char| c.is_whitespace() || c == '/').next()?;
+    Some((name.to_string(), end + 1, closing))
+}
+
+/// the closing tag for an opening one named `name` that ends at `from`,
+/// as (start, end). a tag closing something opened before `from` ends
+/// the search, the opening tag has no pair then
+fn closing_tag(buf: &Buffer, name: &str, from: usize) -> Option<(usize, usize)> {
+    // names of the tags opened since `from`, a closing tag ends the ones
+    // after the last of its name that were left open
+    let mut open: Vec<String> = Vec::new();
+    let mut i = from;
+    while i < buf.len_chars() {
+        if buf.get_char(i) != Some('<') {
+            i += 1;
+            continue;
+        }
+        if starts_with(buf, i, "<!--") {
+            i = (i + 4..buf.len_chars()).find(|&k| starts_with(buf, k, "-->"))? + 3;
+            continue;
+        }
+        match tag_at(buf, i) {
+            Some((n, end, false)) => {
+                open.push(n);
+                i = end;
+            }
+            Some((n, end, true)) => {
+                if let Some(k) = open.iter().rposition(|o| *o == n) {
+                    open.truncate(k);
+                } else if n == name {
+                    return Some((i, end));
+                } else {
+                    return None;
+                }
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+    None
+}
+
+/// `it` and `at`, the innermost pair of matching tags around the cursor,
+/// or the `count`th one out. tags are looked for going back from the
+/// cursor, so that only the text around it is read
+fn tag(buf: &Buffer, at: usize, count: usize, inner: bool) -> Option<Object> {
+    // a tag under the cursor belongs to the pair it opens or closes
+    let mut lt = if buf.get_char(at) == Some('>') {
+        at.saturating_sub(1)
+    } else {
+        at
+    };
+    while lt > 0 && !matches!(buf.get_char(lt), Some('<' | '>')) {
+        lt -= 1;
+    }
+    let mut i = match tag_at(buf, lt) {
+        Some((_, end, closing)) if buf.get_char(lt) == Some('<') && at < end => {
+            if closing {
+                lt
+            } else {
+                end
+            }
+        }
+        _ => at + 1,
+    };
+    // names of tags closed after an opening tag further back, whose pairs
+    // are skipped
+    let mut closed: Vec<String> = Vec::new();
+    let mut levels = count;
+    loop {
+        i = i.checked_sub(1)?;
+        match buf.get_char(i) {
+            Some('>') if i >= 2 && starts_with(buf, i - 2, "-->") => {
+                while !starts_with(buf, i, "<!--") {
+                    i = i.checked_sub(1)?;
+                }
+            }
+            Some('<') => {
+                let Some((name, end, closing)) = tag_at(buf, i) else {
+                    continue;
+                };
+                if closing {
+                    closed.push(name);
+                } else if let Some(k) = closed.iter().rposition(|n| *n == name) {
+                    closed.truncate(k);
+                } else if closed.is_empty() {
+                    let Some((close_start, close_end)) = closing_tag(buf, &name, end) else {
+                        continue;
+                    };
+                    levels -= 1;
+                    if levels == 0 {
+                        return Some(if inner {
+                            Object::chars(end, close_start)
+                        } else {
+                            Object::chars(i, close_end)
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// what `keys` selects with the cursor on the first `|` in `text`,
+    /// which is taken out
+    fn selected(text: &str, keys: &str, count: usize) -> Option<String> {
+        let at = text.find('|').unwrap();
+        let text = text.replacen('|', "", 1);
+        let buf = Buffer::new(&text, PathBuf::from("test.html"));
+        let at = buf.byte_to_char(at);
+        let object = select(&buf, at, keys, count)?;
+        Some(buf.slice(object.start..object.end).to_string())
+    }
+
+    #[test]
+    fn tags_pair_up_around_the_cursor() {
+        let html = "<div><p>one <b>t|wo</b></p><br></div>";
+        assert_eq!(selected(html, "it", 1).as_deref(), Some("two"));
+        assert_eq!(selected(html, "at", 1).as_deref(), Some("<b>two</b>"));
+        assert_eq!(selected(html, "it", 2).as_deref(), Some("one <b>two</b>"));
+        assert_eq!(
+            selected(html, "it", 3).as_deref(),
+            Some("<p>one <b>two</b></p><br>")
+        );
+        assert_eq!(selected(html, "it", 4), None);
+    }
+
+    #[test]
+    fn a_tag_under_the_cursor_is_part_of_its_pair() {
+        let html = "<ul><li>a</li><li>b</l|i></ul>";
+        assert_eq!(selected(html, "it", 1).as_deref(), Some("b"));
+        let html = "<ul><li>a</li|><li>b</li></ul>";
+        assert_eq!(selected(html, "it", 1).as_deref(), Some("a"));
+        let html = "<ul><l|i>a</li><li>b</li></ul>";
+        assert_eq!(selected(html, "at", 1).as_deref(), Some("<li>a</li>"));
+        let html = "<ul><li>a</li>|<li>b</li></ul>";
+        assert_eq!(selected(html, "it", 1).as_deref(), Some("b"));
+        let html = "<ul><li>a</li>|\n<li>b</li></ul>";
+        assert_eq!(
+            selected(html, "it", 1).as_deref(),
+            Some("<li>a</li>\n<li>b</li>")
+        );
+    }
+
+    #[test]
+    fn comments_and_lone_tags_are_skipped() {
+        let html = "<a x=\"1\"><img src=x/><!-- <a> --><br>|x<hr></a>";
+        assert_eq!(
+            selected(html, "it", 1).as_deref(),
+            Some("<img src=x/><!-- <a> --><br>x<hr>")
+        );
+        let html = "<a><a>one</a>|two</a>";
+        assert_eq!(selected(html, "it", 1).as_deref(), Some("<a>one</a>two"));
+    }
+
+    #[test]
+    fn an_unpaired_tag_is_given_up_at_the_end_of_its_parent() {
+        let html = "<ul><li>a<li>|b</ul><li>c</li>";
+        assert_eq!(selected(html, "it", 1).as_deref(), Some("<li>a<li>b"));
+        let html = "<a><b></b><a>x</a>|y</a>";
+        assert_eq!(selected(html, "it", 1).as_deref(), Some("<b></b><a>x</a>y"));
+    }
+
+    #[test]
+    fn words_and_the_blanks_around_them() {
+        let text = "one tw|o.three  four";
+        assert_eq!(selected(text, "iw", 1).as_deref(), Some("two"));
+        assert_eq!(selected(text, "iW", 1).as_deref(), Some("two.three"));
+        // no blank after it, the one before is taken
+        assert_eq!(selected(text, "aw", 1).as_deref(), Some(" two"));
+        assert_eq!(selected(text, "aW", 1).as_deref(), Some("two.three  "));
+        assert_eq!(selected(text, "iw", 3).as_deref(), Some("two.three"));
+        assert_eq!(selected(text, "aw", 2).as_deref(), Some(" two."));
+        let text = "one  tw|o";
+        assert_eq!(selected(text, "aw", 1).as_deref(), Some("  two"));
+        let text = "one | two";
+        assert_eq!(selected(text, "iw", 1).as_deref(), Some("  "));
+        assert_eq!(selected(text, "aw", 1).as_deref(), Some("  two"));
+    }
+
+    #[test]
+    fn sentences_end_at_punctuation_and_a_blank() {
+        let text = "One. Tw|o is (this)! Three.";
+        assert_eq!(selected(text, "is", 1).as_deref(), Some("Two is (this)!"));
+        assert_eq!(selected(text, "as", 1).as_deref(), Some("Two is (this)! "));
+        let text = "One. Two.| Three.";
+        assert_eq!(selected(text, "is", 1).as_deref(), Some(" "));
+    }
+
+    #[test]
+    fn paragraphs_are_split_by_empty_lines() {
+        let text = "a\nb|\n\n\nc\n\nd\n";
+        assert_eq!(selected(text, "ip", 1).as_deref(), Some("a\nb\n"));
+        assert_eq!(selected(text, "ap", 1).as_deref(), Some("a\nb\n\n\n"));
+        assert_eq!(selected(text, "ap", 2).as_deref(), Some("a\nb\n\n\nc\n\n"));
+        let text = "a\n\n|\nc\n";
+        assert_eq!(selected(text, "ip", 1).as_deref(), Some("\n\n"));
+        // the last one takes the empty lines before it when none follow
+        let text = "a\n\nc|\n";
+        assert_eq!(selected(text, "ap", 1).as_deref(), Some("\nc\n"));
+    }
+
+    #[test]
+    fn quotes_pair_up_in_the_line() {
+        let text = "x = \"a \\\" |b\", 'c'";
+        assert_eq!(selected(text, "i\"", 1).as_deref(), Some("a \\\" b"));
+        assert_eq!(selected(text, "a\"", 1).as_deref(), Some(" \"a \\\" b\""));
+        // before the first quote of a kind, the next pair is taken
+        assert_eq!(selected(text, "i'", 1).as_deref(), Some("c"));
+        let text = "x = |'c'";
+        assert_eq!(selected(text, "i'", 1).as_deref(), Some("c"));
+        let text = "f(\"a\"|)";
+        assert_eq!(selected(text, "a\"", 1), None);
+    }
+
+    #[test]
+    fn brackets_nest_and_count_outwards() {
+        let text = "f(a, [b, (|c)], d)";
+        assert_eq!(selected(text, "i(", 1).as_deref(), Some("c"));
+        assert_eq!(selected(text, "a)", 1).as_deref(), Some("(c)"));
+        assert_eq!(selected(text, "ib", 2).as_deref(), Some("a, [b, (c)], d"));
+        assert_eq!(selected(text, "i[", 1).as_deref(), Some("b, (c)"));
+        assert_eq!(selected(text, "i{", 1), None);
+        let text = "f|(a)";
+        assert_eq!(selected(text, "i(", 1).as_deref(), Some("a"));
+        let text = "{\n    |a\n}";
+        assert_eq!(selected(text, "iB", 1).as_deref(), Some("    a\n"));
+    }
+}
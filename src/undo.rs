@@ -0,0 +1,466 @@
+use std::io::{self, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// what an undo file starts with
+const MAGIC: &[u8] = b"ri undo 1\n";
+
+/// one edit of the text, `removed` was taken out at char `at` and
+/// `inserted` put in its place
+#[derive(Clone, PartialEq, Debug)]
+pub struct Edit {
+    pub at: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+/// the edits one command or insert made, taking the text of the state
+/// `parent` to a new one
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub parent: usize,
+    pub edits: Vec<Edit>,
+    /// where the cursor was before the change
+    pub cursor: (usize, usize),
+    /// the buffer's change number for the text after the change
+    pub changenr: usize,
+    pub time: SystemTime,
+    /// the child redo goes to, the one made or undone last
+    pub redo: Option<usize>,
+}
+
+/// the states the text has been in, as a tree of changes. undoing and
+/// then changing the text starts a new branch, the old one stays
+/// reachable with `g-` and `:earlier`
+///
+/// states are numbered in the order they were made, 0 is the text as
+/// it was read
+#[derive(Debug)]
+pub struct UndoTree {
+    changes: Vec<Change>,
+    /// the state the text is in
+    cur: usize,
+    /// edits made since the last change was committed
+    pending: Vec<Edit>,
+    /// where the cursor was when the pending edits started
+    cursor: (usize, usize),
+    /// the line the pending edits are all in, with its text from before
+    /// them, None once they go beyond a line
+    pending_line: Option<(usize, String)>,
+    /// for `U`, the line the last changes were all in and its text from
+    /// before them
+    line: Option<(usize, String)>,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self {
+            changes: vec![Change {
+                parent: 0,
+                edits: Vec::new(),
+                cursor: (0, 0),
+                changenr: 0,
+                time: SystemTime::now(),
+                redo: None,
+            }],
+            cur: 0,
+            pending: Vec::new(),
+            cursor: (0, 0),
+            pending_line: None,
+            line: None,
+        }
+    }
+}
+
+impl UndoTree {
+    /// the number of the current state
+    pub fn seq(&self) -> usize {
+        self.cur
+    }
+
+    /// the number of the newest state
+    pub fn last(&self) -> usize {
+        self.changes.len() - 1
+    }
+
+    pub fn change(&self, seq: usize) -> &Change {
+        &self.changes[seq]
+    }
+
+    /// sets where the cursor is before an edit, ignored while edits
+    /// are pending
+    pub fn set_cursor(&mut self, cursor: (usize, usize)) {
+        if self.pending.is_empty() {
+            self.cursor = cursor;
+        }
+    }
+
+    /// adds an edit made in `line` of the text, whose text before the
+    /// edit was `text`
+    pub fn record(&mut self, edit: Edit, line: usize, text: impl FnOnce() -> String) {
+        let in_line = !edit.removed.contains('\n') && !edit.inserted.contains('\n');
+        if self.pending.is_empty() {
+            self.pending_line = in_line.then(|| (line, text()));
+        } else if !in_line || self.pending_line.as_ref().is_some_and(|(l, _)| *l != line) {
+            self.pending_line = None;
+        }
+        // chars typed one after another make one edit
+        if let Some(last) = self.pending.last_mut() {
+            if edit.removed.is_empty()
+                && last.removed.is_empty()
+                && last.at + last.inserted.chars().count() == edit.at
+            {
+                last.inserted.push_str(&edit.inserted);
+                return;
+            }
+        }
+        self.pending.push(edit);
+    }
+
+    /// makes the pending edits a change, the text they made is change
+    /// number `changenr` of the buffer. false when nothing was pending
+    pub fn commit(&mut self, changenr: usize) -> bool {
+        if self.pending.is_empty() {
+            return false;
+        }
+        let seq = self.changes.len();
+        self.changes.push(Change {
+            parent: self.cur,
+            edits: std::mem::take(&mut self.pending),
+            cursor: self.cursor,
+            changenr,
+            time: SystemTime::now(),
+            redo: None,
+        });
+        self.changes[self.cur].redo = Some(seq);
+        self.cur = seq;
+        match (self.pending_line.take(), &self.line) {
+            (Some((line, _)), Some((last, _))) if line == *last => {}
+            (pending, _) => self.line = pending,
+        }
+        true
+    }
+
+    /// the line `U` puts back and the text it had, taken out so that
+    /// the change `U` makes can be put back by `U` in turn
+    pub fn take_line(&mut self) -> Option<(usize, String)> {
+        self.line.take()
+    }
+
+    /// the newest state made at `time` or before, 0 when the text was
+    /// read after it
+    pub fn at_time(&self, time: SystemTime) -> usize {
+        self.changes
+            .iter()
+            .rposition(|c| c.time <= time)
+            .unwrap_or(0)
+    }
+
+    /// the state `secs` seconds before or after the current one
+    pub fn by_time(&self, secs: u64, later: bool) -> usize {
+        let now = self.changes[self.cur].time;
+        let span = Duration::from_secs(secs);
+        if later {
+            now.checked_add(span)
+                .map_or(self.last(), |t| self.at_time(t))
+                .max(self.cur)
+        } else {
+            now.checked_sub(span)
+                .map_or(0, |t| self.at_time(t))
+                .min(self.cur)
+        }
+    }
+
+    /// moves to state `to`, returning the changes to undo or redo on the
+    /// way there as (state, redo)
+    pub fn goto(&mut self, to: usize) -> Vec<(usize, bool)> {
+        let mut path = vec![to];
+        let mut seq = to;
+        while seq != 0 {
+            seq = self.changes[seq].parent;
+            path.push(seq);
+        }
+        let mut steps = Vec::new();
+        while !path.contains(&self.cur) {
+            let parent = self.changes[self.cur].parent;
+            self.changes[parent].redo = Some(self.cur);
+            steps.push((self.cur, false));
+            self.cur = parent;
+        }
+        let down = path.iter().position(|&seq| seq == self.cur).unwrap_or(0);
+        for &seq in path[..down].iter().rev() {
+            let parent = self.changes[seq].parent;
+            self.changes[parent].redo = Some(seq);
+            steps.push((seq, true));
+        }
+        self.cur = to;
+        self.line = None;
+        steps
+    }
+}
+
+/// true when the edits of every change fit into the text they are made
+/// on, going by the text being `len` chars long in state `cur`. an undo
+/// or redo can then never reach past the end of the text
+fn fits(changes: &[Change], cur: usize, len: usize) -> bool {
+    let chars = |s: &str| s.chars().count();
+    let mut lens = vec![None; changes.len()];
+    lens[cur] = Some(len);
+    // back from the current state to the first one
+    let mut seq = cur;
+    let mut len = len;
+    while seq != 0 {
+        for edit in changes[seq].edits.iter().rev() {
+            let Some(before) = (len + chars(&edit.removed)).checked_sub(chars(&edit.inserted))
+            else {
+                return false;
+            };
+            len = before;
+        }
+        seq = changes[seq].parent;
+        lens[seq] = Some(len);
+    }
+    // then every change from the state before it, which comes first
+    for (seq, change) in changes.iter().enumerate().skip(1) {
+        let Some(mut len) = lens[change.parent] else {
+            return false;
+        };
+        for edit in &change.edits {
+            let removed = chars(&edit.removed);
+            if edit.at.checked_add(removed).is_none_or(|end| end > len) {
+                return false;
+            }
+            len = len - removed + chars(&edit.inserted);
+        }
+        if lens[seq].is_some_and(|l| l != len) {
+            return false;
+        }
+        lens[seq] = Some(len);
+    }
+    true
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+fn put<W: Write>(w: &mut W, n: usize) -> io::Result<()> {
+    w.write_all(&(n as u64).to_le_bytes())
+}
+
+fn put_str<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    put(w, s.len())?;
+    w.write_all(s.as_bytes())
+}
+
+fn get<R: Read>(r: &mut R) -> io::Result<usize> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    usize::try_from(u64::from_le_bytes(bytes)).map_err(|_| invalid("number too large"))
+}
+
+fn get_str<R: Read>(r: &mut R) -> io::Result<String> {
+    let len = get(r)?;
+    // a broken length must not make us allocate it all up front
+    let mut bytes = Vec::new();
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("text is not utf-8"))
+}
+
+impl UndoTree {
+    /// writes the changes for an undo file, `hash` is that of the text
+    /// in the current state
+    pub fn write_to<W: Write>(&self, mut w: W, hash: u64) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&hash.to_le_bytes())?;
+        put(&mut w, self.cur)?;
+        put(&mut w, self.changes.len())?;
+        for change in &self.changes {
+            let time = change
+                .time
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            put(&mut w, change.parent)?;
+            put(&mut w, change.cursor.0)?;
+            put(&mut w, change.cursor.1)?;
+            put(&mut w, time as usize)?;
+            put(&mut w, change.redo.map_or(0, |redo| redo + 1))?;
+            put(&mut w, change.edits.len())?;
+            for edit in &change.edits {
+                put(&mut w, edit.at)?;
+                put_str(&mut w, &edit.removed)?;
+                put_str(&mut w, &edit.inserted)?;
+            }
+        }
+        w.flush()
+    }
+
+    /// reads what `write_to` wrote, None when it was written for a text
+    /// other than the one with `hash`. `len` is the length of that text
+    /// in chars. states get their number as change number
+    pub fn read_from<R: Read>(mut r: R, hash: u64, len: usize) -> io::Result<Option<Self>> {
+        let mut magic = [0; MAGIC.len()];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid("not an undo file"));
+        }
+        let mut bytes = [0; 8];
+        r.read_exact(&mut bytes)?;
+        if u64::from_le_bytes(bytes) != hash {
+            return Ok(None);
+        }
+        let cur = get(&mut r)?;
+        let count = get(&mut r)?;
+        let mut changes = Vec::new();
+        for seq in 0..count {
+            let parent = get(&mut r)?;
+            let cursor = (get(&mut r)?, get(&mut r)?);
+            let time = UNIX_EPOCH + Duration::from_secs(get(&mut r)? as u64);
+            let redo = get(&mut r)?.checked_sub(1);
+            if (seq > 0 && parent >= seq) || redo.is_some_and(|redo| redo >= count) {
+                return Err(invalid("broken undo tree"));
+            }
+            let mut edits = Vec::new();
+            for _ in 0..get(&mut r)? {
+                edits.push(Edit {
+                    at: get(&mut r)?,
+                    removed: get_str(&mut r)?,
+                    inserted: get_str(&mut r)?,
+                });
+            }
+            changes.push(Change {
+                parent,
+                edits,
+                cursor,
+                changenr: seq,
+                time,
+                redo,
+            });
+        }
+        if cur >= changes.len() || !fits(&changes, cur, len) {
+            return Err(invalid("broken undo tree"));
+        }
+        Ok(Some(Self {
+            changes,
+            cur,
+            ..Self::default()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// commits a change inserting `text` at the start, as the next state
+    fn change(tree: &mut UndoTree, text: &str) {
+        let edit = Edit {
+            at: 0,
+            removed: String::new(),
+            inserted: text.to_string(),
+        };
+        tree.record(edit, 0, String::new);
+        assert!(tree.commit(tree.last() + 1));
+    }
+
+    #[test]
+    fn going_to_a_state_undoes_up_to_the_branch_and_redoes_down_it() {
+        let mut tree = UndoTree::default();
+        change(&mut tree, "a");
+        change(&mut tree, "b");
+        assert_eq!(tree.goto(1), [(2, false)]);
+        change(&mut tree, "c");
+        assert_eq!(tree.change(3).parent, 1);
+        assert_eq!(tree.goto(2), [(3, false), (2, true)]);
+        assert_eq!(tree.goto(0), [(2, false), (1, false)]);
+        // redo follows the branch visited last
+        assert_eq!(tree.change(1).redo, Some(2));
+        assert_eq!(tree.goto(3), [(1, true), (3, true)]);
+        assert_eq!(tree.change(1).redo, Some(3));
+        assert_eq!(tree.goto(3), []);
+        assert_eq!(tree.seq(), 3);
+    }
+
+    #[test]
+    fn nothing_pending_commits_nothing() {
+        let mut tree = UndoTree::default();
+        assert!(!tree.commit(1));
+        assert_eq!(tree.last(), 0);
+        // typing on makes one edit
+        for (at, c) in "abc".chars().enumerate() {
+            let edit = Edit {
+                at,
+                removed: String::new(),
+                inserted: c.to_string(),
+            };
+            tree.record(edit, 0, String::new);
+        }
+        assert!(tree.commit(1));
+        assert_eq!(tree.change(1).edits.len(), 1);
+        assert_eq!(tree.change(1).edits[0].inserted, "abc");
+    }
+
+    #[test]
+    fn states_are_found_by_time() {
+        let mut tree = UndoTree::default();
+        change(&mut tree, "a");
+        change(&mut tree, "b");
+        change(&mut tree, "c");
+        let start = UNIX_EPOCH + Duration::from_secs(1000);
+        for (seq, secs) in [0, 10, 20, 100].into_iter().enumerate() {
+            tree.changes[seq].time = start + Duration::from_secs(secs);
+        }
+        assert_eq!(tree.at_time(start + Duration::from_secs(15)), 1);
+        assert_eq!(tree.at_time(start - Duration::from_secs(1)), 0);
+        assert_eq!(tree.by_time(80, false), 2);
+        assert_eq!(tree.by_time(85, false), 1);
+        assert_eq!(tree.by_time(1000, false), 0);
+        tree.goto(1);
+        assert_eq!(tree.by_time(10, true), 2);
+        assert_eq!(tree.by_time(5, true), 1);
+        assert_eq!(tree.by_time(1000, true), 3);
+    }
+
+    #[test]
+    fn an_undo_file_reads_back_what_was_written() {
+        let mut tree = UndoTree::default();
+        change(&mut tree, "a");
+        change(&mut tree, "b");
+        tree.goto(1);
+        let mut file = Vec::new();
+        tree.write_to(&mut file, 7).unwrap();
+        assert!(UndoTree::read_from(&file[..], 8, 1).unwrap().is_none());
+        let read = UndoTree::read_from(&file[..], 7, 1).unwrap().unwrap();
+        assert_eq!((read.seq(), read.last()), (1, 2));
+        assert_eq!(read.change(2).edits, tree.change(2).edits);
+        assert_eq!(read.change(1).redo, Some(2));
+        assert!(UndoTree::read_from(&file[..file.len() - 1], 7, 1).is_err());
+    }
+
+    #[test]
+    fn edits_that_do_not_fit_the_text_are_rejected() {
+        let mut tree = UndoTree::default();
+        change(&mut tree, "ab");
+        change(&mut tree, "c");
+        let read = |tree: &UndoTree, len| {
+            let mut file = Vec::new();
+            tree.write_to(&mut file, 7).unwrap();
+            UndoTree::read_from(&file[..], 7, len)
+        };
+        assert!(read(&tree, 3).is_ok());
+        // the text is too short for what was inserted into it
+        assert!(read(&tree, 2).is_err());
+        // an edit past the end of the text on another branch
+        tree.goto(1);
+        change(&mut tree, "d");
+        tree.changes[3].edits[0].at = 5;
+        assert!(read(&tree, 3).is_err());
+        tree.changes[3].edits[0].at = 2;
+        assert!(read(&tree, 3).is_ok());
+        // more taken out than the text before it had
+        tree.changes[2].edits[0].removed = "xyz".to_string();
+        assert!(read(&tree, 3).is_err());
+    }
+}
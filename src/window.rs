@@ -0,0 +1,398 @@
+use std::cmp::min;
+
+use crate::buffer::Buffer;
+use crate::register::Kind;
+use crate::screen::{self, Frame, Style, GUTTER, SELECTION, TEXT};
+
+/// the part of a buffer covered by visual mode, both ends included
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub kind: Kind,
+    /// the first and last column of a block, no last one when the block
+    /// goes to the end of every line
+    pub left: usize,
+    pub right: Option<usize>,
+}
+
+impl Selection {
+    /// the selection between two positions given in any order, `to_eol`
+    /// makes a block go to the end of every line
+    pub fn new(a: (usize, usize), b: (usize, usize), kind: Kind, to_eol: bool) -> Self {
+        Self {
+            start: a.min(b),
+            end: a.max(b),
+            kind,
+            left: a.1.min(b.1),
+            right: (!to_eol).then(|| a.1.max(b.1)),
+        }
+    }
+
+    /// columns of `line` that are selected, the end is exclusive and may
+    /// go one past the text to show the selected line break
+    fn cols(&self, line: usize, len: usize) -> Option<(usize, usize)> {
+        if line < self.start.0 || line > self.end.0 {
+            return None;
+        }
+        match self.kind {
+            Kind::Linewise => return Some((0, len + 1)),
+            Kind::Blockwise => {
+                let right = self.right.map_or(len, |right| (right + 1).min(len));
+                return Some((self.left.min(len), right));
+            }
+            Kind::Charwise => {}
+        }
+        let from = if line == self.start.0 {
+            self.start.1
+        } else {
+            0
+        };
+        let to = if line == self.end.0 {
+            self.end.1 + 1
+        } else {
+            len + 1
+        };
+        Some((from, to))
+    }
+}
+
+/// where `z` commands put the cursor line
+#[derive(Clone, Copy, Debug)]
+pub enum ScreenPos {
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// a view onto a buffer, owns the cursor and the scroll state
+///
+/// cursor and offsets are buffer positions, the screen position of the
+/// cursor is derived from them when drawing
+#[derive(Debug)]
+pub struct Window {
+    /// index into `Editor::buffers`
+    pub buf: usize,
+    /// cursor column, in chars
+    pub cx: usize,
+    /// cursor line
+    pub cy: usize,
+    /// column to go back to when moving up and down
+    pub max_x: usize,
+    /// true when END has been pressed
+    /// and left/HOME key hasn't been pressed
+    pub rightted: bool,
+    /// first line shown
+    pub rowoff: usize,
+    /// first screen column shown, tabs take up more than one
+    pub coloff: usize,
+    /// screen column (1 based) where the text starts, after the line numbers
+    pub cx_base: usize,
+    /// first screen row (0 based)
+    pub top: usize,
+    /// number of text rows
+    pub height: usize,
+    /// number of screen columns
+    pub width: usize,
+    /// where visual mode started, the other end is the cursor
+    pub anchor: Option<(usize, usize)>,
+    /// columns a tab takes up, kept in sync with the option
+    pub tabstop: usize,
+}
+
+/// columns `c` takes up on screen when it starts at column `vcol`
+pub fn char_width(c: char, vcol: usize, tabstop: usize) -> usize {
+    if c == '\t' {
+        tabstop - vcol % tabstop
+    } else {
+        screen::width(c)
+    }
+}
+
+impl Window {
+    pub fn new(buf: usize) -> Self {
+        Self {
+            buf,
+            cx: 0,
+            cy: 0,
+            max_x: 0,
+            rightted: false,
+            rowoff: 0,
+            coloff: 0,
+            cx_base: 0,
+            top: 0,
+            height: 1,
+            width: 1,
+            anchor: None,
+            tabstop: 8,
+        }
+    }
+
+    /// switches the window to another buffer, back at its first line
+    pub fn show(&mut self, buf: usize) {
+        *self = Self {
+            top: self.top,
+            height: self.height,
+            width: self.width,
+            tabstop: self.tabstop,
+            ..Self::new(buf)
+        };
+    }
+
+    /// number of columns left for text after the line numbers
+    pub fn text_cols(&self) -> usize {
+        (self.width + 1).saturating_sub(self.cx_base).max(1)
+    }
+
+    /// the screen column char `col` of `line` starts at, counted from the
+    /// start of the text. past the end every column is one wide
+    pub fn vcol(&self, buf: &Buffer, line: usize, col: usize) -> usize {
+        let text = buf.line(line);
+        let past = col.saturating_sub(text.len_chars());
+        let vcol = text
+            .chars()
+            .take(col)
+            .fold(0, |vcol, c| vcol + char_width(c, vcol, self.tabstop));
+        vcol + past
+    }
+
+    /// the char of `line` shown at screen column `vcol`, the end of the
+    /// line when it is past the text
+    pub fn col_at(&self, buf: &Buffer, line: usize, vcol: usize) -> usize {
+        let mut at = 0;
+        for (col, c) in buf.line(line).chars().enumerate() {
+            at += char_width(c, at, self.tabstop);
+            if vcol < at {
+                return col;
+            }
+        }
+        buf.line_len(line)
+    }
+
+    pub fn set_x_after_up_down(&mut self, buf: &Buffer) {
+        let len = buf.line_len(self.cy);
+        self.cx = if self.rightted {
+            len
+        } else {
+            min(self.max_x, len)
+        };
+    }
+
+    pub fn move_left(&mut self) {
+        if self.cx > 0 {
+            self.cx -= 1;
+        }
+        self.max_x = self.cx;
+        self.rightted = false;
+    }
+
+    pub fn move_right(&mut self, buf: &Buffer) {
+        if self.cx < buf.line_len(self.cy) {
+            self.cx += 1;
+        }
+        self.max_x = self.cx;
+    }
+
+    pub fn move_up(&mut self, buf: &Buffer) {
+        if self.cy > 0 {
+            self.cy -= 1;
+        }
+        self.set_x_after_up_down(buf);
+    }
+
+    pub fn move_down(&mut self, buf: &Buffer) {
+        if self.cy < buf.last_line() {
+            self.cy += 1;
+        }
+        self.set_x_after_up_down(buf);
+    }
+
+    pub fn home(&mut self) {
+        self.cx = 0;
+        self.max_x = 0;
+        self.rightted = false;
+    }
+
+    pub fn end(&mut self, buf: &Buffer) {
+        self.cx = buf.line_len(self.cy);
+        self.max_x = self.cx;
+        self.rightted = true;
+    }
+
+    /// lines a page scrolls by, two stay in view for context
+    fn page(&self) -> usize {
+        self.height.saturating_sub(2).max(1)
+    }
+
+    /// Ctrl-B, scrolls `count` pages back with the cursor on the last
+    /// line shown, false when already at the top
+    pub fn page_up(&mut self, count: usize, buf: &Buffer) -> bool {
+        if self.rowoff == 0 {
+            return false;
+        }
+        self.rowoff = self.rowoff.saturating_sub(self.page() * count);
+        self.cy = min(self.cy, self.rowoff + self.height - 1);
+        self.set_x_after_up_down(buf);
+        true
+    }
+
+    /// Ctrl-F, scrolls `count` pages forward with the cursor on the first
+    /// line shown, false when the last line is already at the top
+    pub fn page_down(&mut self, count: usize, buf: &Buffer) -> bool {
+        let last = buf.last_line();
+        if self.rowoff >= last {
+            return false;
+        }
+        self.rowoff = min(self.rowoff + self.page() * count, last);
+        self.cy = self.cy.max(self.rowoff);
+        self.set_x_after_up_down(buf);
+        true
+    }
+
+    /// Ctrl-D and Ctrl-U, scroll the view and the cursor by `lines` or half
+    /// a window, false when the cursor is already on the last or first line
+    pub fn scroll_half(&mut self, down: bool, lines: Option<usize>, buf: &Buffer) -> bool {
+        let n = lines.unwrap_or(self.height / 2).max(1);
+        let last = buf.last_line();
+        if down {
+            if self.cy >= last {
+                return false;
+            }
+            let max_rowoff = (last + 1).saturating_sub(self.height);
+            self.rowoff = min(self.rowoff + n, max_rowoff.max(self.rowoff));
+            self.cy = min(self.cy + n, last);
+        } else {
+            if self.cy == 0 {
+                return false;
+            }
+            self.rowoff = self.rowoff.saturating_sub(n);
+            self.cy = self.cy.saturating_sub(n);
+        }
+        self.set_x_after_up_down(buf);
+        true
+    }
+
+    /// zt, zz and zb, scroll so that the cursor line is at the top, in the
+    /// middle or at the bottom
+    pub fn scroll_cursor_to(&mut self, at: ScreenPos) {
+        self.rowoff = match at {
+            ScreenPos::Top => self.cy,
+            ScreenPos::Middle => self.cy.saturating_sub((self.height - 1) / 2),
+            ScreenPos::Bottom => (self.cy + 1).saturating_sub(self.height),
+        };
+    }
+
+    /// moves the view `delta` lines down or up, taking the cursor along
+    /// when it would go out of sight
+    pub fn scroll_by(&mut self, delta: isize, buf: &Buffer) {
+        let last = buf.last_line();
+        self.rowoff = self.rowoff.saturating_add_signed(delta).min(last);
+        if self.cy < self.rowoff {
+            self.cy = self.rowoff;
+        } else if self.cy >= self.rowoff + self.height {
+            self.cy = self.rowoff + self.height - 1;
+        }
+        self.set_x_after_up_down(buf);
+    }
+
+    /// buffer position shown at the 0 based screen `row` and `col`,
+    /// clamped to the text when pointing past it
+    pub fn screen_to_pos(&self, buf: &Buffer, row: usize, col: usize) -> (usize, usize) {
+        let line = min(self.rowoff + row.saturating_sub(self.top), buf.last_line());
+        let vcol = self.coloff + (col + 1).saturating_sub(self.cx_base);
+        (line, self.col_at(buf, line, vcol))
+    }
+
+    /// true when the 0 based screen `col` is in the line number column
+    pub fn in_gutter(&self, col: usize) -> bool {
+        col + 1 < self.cx_base
+    }
+
+    /// puts the cursor at (line, col) and makes it the new sticky column
+    pub fn cursor_to(&mut self, line: usize, col: usize) {
+        self.cy = line;
+        self.cx = col;
+        self.max_x = col;
+        self.rightted = false;
+    }
+
+    /// keeps the cursor inside the buffer and the buffer scrolled so that
+    /// the cursor is visible
+    pub fn scroll(&mut self, buf: &Buffer) {
+        self.cx_base = buf.line_count().to_string().len() + 4;
+        self.cy = min(self.cy, buf.line_count() - 1);
+        self.cx = min(self.cx, buf.line_len(self.cy));
+        if self.cy < self.rowoff {
+            self.rowoff = self.cy;
+        } else if self.cy >= self.rowoff + self.height {
+            self.rowoff = self.cy + 1 - self.height;
+        }
+        let textcols = self.text_cols();
+        let vx = self.vcol(buf, self.cy, self.cx);
+        if vx < self.coloff {
+            self.coloff = vx;
+        } else if vx >= self.coloff + textcols {
+            self.coloff = vx + 1 - textcols;
+        }
+    }
+
+    /// screen position (1 based row, col) of the cursor
+    pub fn screen_cursor(&self, buf: &Buffer) -> (usize, usize) {
+        (
+            self.top + self.cy - self.rowoff + 1,
+            self.cx_base + self.vcol(buf, self.cy, self.cx) - self.coloff,
+        )
+    }
+
+    pub fn draw(&self, buf: &Buffer, selection: Option<Selection>, frame: &mut Frame) {
+        let textcols = self.text_cols();
+        for i in 0..self.height {
+            let row = self.top + i;
+            let lineno = self.rowoff + i;
+            if lineno >= buf.line_count() {
+                // if space is left, fill it with tildes
+                frame.put(row, 0, "~".chars(), Style::PLAIN);
+                continue;
+            }
+            let mut rowstr = format!(" {} ", lineno + 1);
+            while rowstr.len() < self.cx_base - 2 {
+                rowstr.insert(0, ' ');
+            }
+            let col = frame.put(row, 0, rowstr.chars(), GUTTER);
+            let col = frame.put(row, col, " ".chars(), TEXT);
+            let line = buf.line(lineno);
+            // the chars in view, tabs are spread over the columns they take
+            // and a wide char cut by the left edge shows as blanks
+            let mut shown = String::new();
+            let mut vcol = 0;
+            for c in line.chars() {
+                let width = char_width(c, vcol, self.tabstop);
+                let cells = (vcol..vcol + width).filter(|&v| v >= self.coloff).count();
+                if c != '\t' && cells == width {
+                    shown.push(c);
+                } else {
+                    shown.extend(std::iter::repeat_n(' ', cells));
+                }
+                vcol += width;
+                if vcol >= self.coloff + textcols {
+                    break;
+                }
+            }
+            let end = frame.put(row, col, shown.chars().take(textcols), TEXT);
+            // blank space to the end of the line
+            frame.fill(row, end, col + textcols, TEXT);
+            if let Some((from, to)) = selection.and_then(|s| s.cols(lineno, line.len_chars())) {
+                let from = self.vcol(buf, lineno, from).max(self.coloff);
+                let to = self.vcol(buf, lineno, to).min(self.coloff + textcols);
+                if from < to {
+                    frame.restyle(
+                        row,
+                        col + from - self.coloff,
+                        col + to - self.coloff,
+                        SELECTION,
+                    );
+                }
+            }
+        }
+    }
+}